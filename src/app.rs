@@ -0,0 +1,791 @@
+use std::{
+    cmp::min,
+    collections::VecDeque,
+    ffi::OsString,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    time::Duration,
+};
+
+use crossterm::event::{Event, EventStream};
+use futures::{channel::mpsc::UnboundedReceiver, select_biased, FutureExt, StreamExt};
+use terminal_vec2::Vec2;
+use tokio::{task::JoinHandle, time::sleep};
+
+use crate::{
+    config::MmmConfig,
+    error_type::MmmResult,
+    filesystem::{
+        picker::MmmPicker,
+        shell::{expand_placeholders, quote_paths, run_quiet_shell_command},
+        MmmFilesys,
+    },
+    terminal::{
+        canvas::MmmCanvas,
+        draw::DrawOps,
+        events::{
+            decode_crossterm_event, get_state_update_type, process_state_update, MmmClickTracker,
+            MmmStateUpdateType,
+        },
+        layout::MmmLayout,
+        status::MmmStatus,
+    },
+};
+
+/// Where the main loop reads terminal events from
+pub trait MmmEventSource {
+    /// The next event, `None` once there will be no more
+    fn next_event(&mut self) -> impl Future<Output = Option<io::Result<Event>>>;
+    /// Called before the display is handed to another program
+    fn reset(&mut self) {}
+}
+
+/// Events read from the real terminal
+pub struct MmmTerminalEvents {
+    stream: EventStream,
+}
+
+impl Default for MmmTerminalEvents {
+    fn default() -> Self {
+        MmmTerminalEvents {
+            stream: EventStream::new(),
+        }
+    }
+}
+
+impl MmmEventSource for MmmTerminalEvents {
+    fn next_event(&mut self) -> impl Future<Output = Option<io::Result<Event>>> {
+        self.stream.next()
+    }
+
+    fn reset(&mut self) {
+        // Drop the old stream so its reader thread stops consuming stdin
+        self.stream = EventStream::new();
+    }
+}
+
+/// A signal asking mmm to stop, which ends the main loop so the terminal can be given back
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MmmSignal {
+    pub name: &'static str,
+    pub number: i32,
+}
+
+/// The next stop signal, never resolving when there are none to wait for
+async fn next_signal(signals: &mut Option<UnboundedReceiver<MmmSignal>>) -> MmmSignal {
+    if let Some(signal) = signals.as_mut().map(|signals| signals.next()) {
+        if let Some(signal) = signal.await {
+            return signal;
+        }
+    }
+    std::future::pending().await
+}
+
+/// Everything the main loop works on, kept apart from the terminal so it can be driven with
+/// any event source, canvas and size
+pub struct MmmApp {
+    pub layout: MmmLayout,
+    pub filesys: MmmFilesys,
+    pub status: MmmStatus,
+    /// Run with the picked paths each time something is picked, instead of stopping
+    pub on_select: Option<String>,
+    /// On select commands waiting for the one running to finish, so they run in pick order
+    on_select_queue: VecDeque<MmmOnSelectRun>,
+    on_select_running: Option<(MmmOnSelectRun, JoinHandle<io::Result<ExitStatus>>)>,
+    /// How many picks have been handed to the on select command
+    on_select_picks: usize,
+    /// Stop signals forwarded from the OS, handled in the loop so nothing else touches the
+    /// terminal while it is being drawn
+    pub signals: Option<UnboundedReceiver<MmmSignal>>,
+    /// The signal that ended the loop, if one did
+    pub stopped_by: Option<MmmSignal>,
+    clicks: MmmClickTracker,
+}
+
+/// An on select command filled in with what was picked
+struct MmmOnSelectRun {
+    command: OsString,
+    cwd: PathBuf,
+    picked: usize,
+}
+
+impl MmmApp {
+    pub fn new(
+        path: PathBuf,
+        term_size: Vec2,
+        config: &MmmConfig,
+        data_home: Option<&Path>,
+        picker: Option<MmmPicker>,
+        status: MmmStatus,
+    ) -> MmmResult<Self> {
+        let layout = MmmLayout::from_size(term_size)?;
+        let mut filesys = MmmFilesys::from_path(path, data_home)?;
+        filesys.remember_selection = config.remember_selection;
+        filesys.physical_paths = config.physical_paths;
+        filesys.auto_enter = config.auto_enter;
+        filesys.commands = config.commands.clone();
+        filesys.picker = picker;
+        Ok(MmmApp {
+            layout,
+            filesys,
+            status,
+            on_select: None,
+            on_select_queue: VecDeque::new(),
+            on_select_running: None,
+            on_select_picks: 0,
+            signals: None,
+            stopped_by: None,
+            clicks: MmmClickTracker::default(),
+        })
+    }
+
+    /// Handles events until one asks to exit or the source runs dry
+    pub async fn run(
+        &mut self,
+        events: &mut impl MmmEventSource,
+        canvas: &mut impl MmmCanvas,
+    ) -> MmmResult<()> {
+        // Draw the first frame before waiting for anything, the layout is already sized for it
+        self.draw(DrawOps::full(), canvas)?;
+
+        loop {
+            // Tick while jobs or on select commands are running so their progress is redrawn and
+            // when the status message expires, otherwise wait for a year
+            let mut tick = if self.filesys.jobs_active() || self.on_select_running.is_some() {
+                Duration::from_millis(100)
+            } else {
+                Duration::from_secs(31536000)
+            };
+            if let Some(expiry) = self.status.time_to_expiry() {
+                tick = min(tick, expiry);
+            }
+            let mut timer = Box::pin(sleep(tick)).fuse();
+            let mut terminal_event_future = Box::pin(events.next_event()).fuse();
+            let mut signal_future = Box::pin(next_signal(&mut self.signals)).fuse();
+            let mut terminal_event = None;
+            let mut ticked = false;
+            let mut signal = None;
+
+            // Wait for an event, the only async section, this needs to be async for the event stream to work.
+            // A stop signal goes first so it isn't held up behind a backlog of input.
+            select_biased! {
+                signal_local = signal_future => signal = Some(signal_local),
+                terminal_event_local = terminal_event_future => {
+                    match terminal_event_local {
+                        Some(te) => terminal_event = Some(te?),
+                        None => break,
+                    }
+                },
+                _ = timer => ticked = true,
+            }
+            drop(terminal_event_future);
+            drop(signal_future);
+            if signal.is_some() {
+                self.stopped_by = signal;
+                break;
+            }
+
+            if self.poll_on_select().await {
+                self.draw(DrawOps::status_line(), canvas)?;
+            }
+
+            // State update logic
+            let state_update_option = if ticked {
+                Some(MmmStateUpdateType::Tick)
+            } else {
+                decode_crossterm_event(terminal_event, self.layout.display, &mut self.clicks)
+                    .and_then(|event| get_state_update_type(event, &self.filesys))
+            };
+            if state_update_option.is_none() {
+                continue;
+            }
+            let draw_ops = match state_update_option.expect("illegal state_update_option state") {
+                // Leaving would kill a copy or move half way through, before it can clean up or be
+                // recorded for undo
+                MmmStateUpdateType::Exit if self.filesys.jobs_active() => {
+                    self.status.error("jobs running, Ctrl-c to cancel");
+                    DrawOps::status_line()
+                }
+                MmmStateUpdateType::Exit => {
+                    break;
+                }
+                state_update => {
+                    if state_update.suspends_display() {
+                        events.reset();
+                    }
+                    self.update(state_update)?
+                }
+            };
+
+            // A picker is done as soon as something has been picked, unless there is a command
+            // to hand each pick to
+            if self.filesys.get_picked().is_some() {
+                if self.on_select.is_none() {
+                    break;
+                }
+                self.queue_on_select();
+            }
+
+            // Rendering logic
+            self.draw(draw_ops, canvas)?;
+        }
+        // Let the picks already made finish opening rather than cutting them off, unless mmm is
+        // being told to stop
+        while self.on_select_running.is_some() && self.stopped_by.is_none() {
+            if let Some((_, handle)) = self.on_select_running.take() {
+                let _ = handle.await;
+            }
+            self.start_next_on_select();
+        }
+        Ok(())
+    }
+
+    /// How many picks were handed to the on select command
+    pub fn get_on_select_picks(&self) -> usize {
+        self.on_select_picks
+    }
+
+    /// Applies a state update, only fatal errors are returned
+    fn update(&mut self, state_update: MmmStateUpdateType) -> MmmResult<DrawOps> {
+        match process_state_update(
+            state_update,
+            &mut self.layout,
+            &mut self.filesys,
+            &mut self.status,
+        ) {
+            Ok(draw_ops) => Ok(draw_ops),
+            Err(err) if err.is_fatal() => Err(err),
+            Err(err) => {
+                // The state is left as it was, so show the error and redraw everything in case
+                // the display was suspended when it happened
+                self.status.error(err.to_string());
+                Ok(DrawOps::full())
+            }
+        }
+    }
+
+    /// Queues the on select command with `{}` replaced by the picked paths, or with them added to
+    /// the end when there is no `{}`, and carries on browsing
+    fn queue_on_select(&mut self) {
+        let (Some(template), Some(picked)) = (&self.on_select, self.filesys.get_picked()) else {
+            return;
+        };
+        let paths = quote_paths(picked);
+        let command = if template.contains("{}") {
+            expand_placeholders(template, |name| name.is_empty().then(|| paths.clone()))
+        } else {
+            let mut command = OsString::from(template);
+            command.push(" ");
+            command.push(paths);
+            command
+        };
+        self.on_select_queue.push_back(MmmOnSelectRun {
+            command,
+            cwd: self.filesys.get_current_path().to_path_buf(),
+            picked: picked.len(),
+        });
+        self.on_select_picks += 1;
+        self.filesys.finish_pick();
+        self.start_next_on_select();
+    }
+
+    fn start_next_on_select(&mut self) {
+        if self.on_select_running.is_some() {
+            return;
+        }
+        if let Some(run) = self.on_select_queue.pop_front() {
+            let handle = tokio::spawn(run_quiet_shell_command(
+                run.command.clone(),
+                run.cwd.clone(),
+            ));
+            self.on_select_running = Some((run, handle));
+        }
+    }
+
+    /// Reports the on select command once it finishes and starts the next one, returns whether
+    /// one finished
+    async fn poll_on_select(&mut self) -> bool {
+        match &self.on_select_running {
+            Some((_, handle)) if handle.is_finished() => (),
+            _ => return false,
+        }
+        let Some((run, handle)) = self.on_select_running.take() else {
+            return false;
+        };
+        match handle.await {
+            Ok(Ok(exit)) if exit.success() => self.status.info(format!("picked {}", run.picked)),
+            Ok(Ok(exit)) => self
+                .status
+                .error(format!("on select command failed, {}", exit)),
+            Ok(Err(err)) => self
+                .status
+                .error(format!("on select command failed, {}", err)),
+            Err(err) => self
+                .status
+                .error(format!("on select command failed, {}", err)),
+        }
+        self.start_next_on_select();
+        true
+    }
+
+    fn draw(&mut self, draw_ops: DrawOps, canvas: &mut impl MmmCanvas) -> MmmResult<()> {
+        self.filesys
+            .keep_selection_in_view(self.layout.current_size.row as usize);
+        if draw_ops.is_any() {
+            draw_ops.draw(canvas, &self.filesys, &self.layout, &self.status)?;
+            canvas.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crossterm::event::{
+        KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    };
+    use terminal_vec2::vec2;
+
+    use super::*;
+    use crate::{
+        filesystem::{picker::MmmPickKind, shell::quote_path, MmmMode},
+        terminal::{canvas::MmmTextCanvas, display::MmmDisplay},
+        test_util::{key, make_data_home, make_tree, run_app, run_script, type_str},
+    };
+
+    async fn run_picker(
+        path: PathBuf,
+        picker: MmmPicker,
+        events: Vec<Event>,
+    ) -> (MmmApp, MmmTextCanvas) {
+        let data_home = make_data_home();
+        let app = MmmApp::new(
+            path,
+            vec2!(60, 20).unwrap(),
+            &MmmConfig::default(),
+            Some(&data_home),
+            Some(picker),
+            MmmStatus::default(),
+        )
+        .unwrap();
+        run_app(app, events).await.unwrap()
+    }
+
+    fn get_selected_name(app: &MmmApp) -> Option<String> {
+        app.filesys
+            .get_selected_entry()
+            .map(|entry| entry.get_name().to_string())
+    }
+
+    #[tokio::test]
+    async fn filter_and_space_enter_a_directory() {
+        let root = make_tree("enter", &["alpha/one", "beta"], &["gamma.txt"]);
+        let mut events = type_str("alp");
+        events.push(key(KeyCode::Char(' ')));
+        let (app, canvas) = run_script(root.clone(), vec2!(60, 20).unwrap(), events)
+            .await
+            .unwrap();
+        assert_eq!(app.filesys.get_current_path(), root.join("alpha"));
+        assert!(app.filesys.filter_is_empty());
+        assert!(canvas.get_frame().contains("one"));
+    }
+
+    #[tokio::test]
+    async fn backspace_goes_up_to_the_directory_left() {
+        let root = make_tree("up", &["alpha", "beta/inner"], &[]);
+        let mut events = type_str("be");
+        events.push(key(KeyCode::Char(' ')));
+        events.push(key(KeyCode::Backspace));
+        let (app, _) = run_script(root.clone(), vec2!(60, 20).unwrap(), events)
+            .await
+            .unwrap();
+        assert_eq!(app.filesys.get_current_path(), *root);
+        assert_eq!(get_selected_name(&app).as_deref(), Some("beta"));
+    }
+
+    #[tokio::test]
+    async fn escape_stops_the_script() {
+        let root = make_tree("escape", &["alpha"], &[]);
+        let mut events = vec![key(KeyCode::Esc)];
+        events.extend(type_str("xyz"));
+        let (app, _) = run_script(root.clone(), vec2!(60, 20).unwrap(), events)
+            .await
+            .unwrap();
+        assert!(app.filesys.filter_is_empty());
+    }
+
+    #[tokio::test]
+    async fn escape_does_not_stop_a_running_copy() {
+        let root = make_tree("escape-copy", &["alpha", "beta"], &["alpha/one.txt"]);
+        let ctrl = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL));
+        let mut events = vec![ctrl('y')];
+        events.extend(type_str("beta "));
+        events.push(ctrl('p'));
+        events.push(key(KeyCode::Esc));
+        // Typed after Esc, so only seen if Esc didn't end the loop
+        events.extend(type_str("zz"));
+        let (app, canvas) = run_script(root.clone(), vec2!(60, 20).unwrap(), events)
+            .await
+            .unwrap();
+        assert_eq!(app.filesys.get_current_path(), root.join("beta"));
+        assert_eq!(app.filesys.get_filter(), "zz");
+        assert!(canvas
+            .get_frame()
+            .contains("jobs running, Ctrl-c to cancel"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn non_utf8_directory_is_found_in_its_parent() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        let root = make_tree("non-utf8", &["alpha", "beta"], &[]);
+        let dir = root.join(OsStr::from_bytes(b"caf\xe9"));
+        fs::create_dir(&dir).unwrap();
+        let (app, _) = run_script(dir.clone(), vec2!(60, 20).unwrap(), vec![])
+            .await
+            .unwrap();
+        let parents = app.filesys.filtered_parent_dir_list.as_ref().unwrap();
+        assert_eq!(parents[app.filesys.parent_current_entry].get_path(), dir);
+    }
+
+    #[tokio::test]
+    async fn frame_shows_the_listing_and_path() {
+        let root = make_tree("frame", &["alpha", "beta"], &["gamma.txt"]);
+        let (_, canvas) = run_script(root.clone(), vec2!(60, 20).unwrap(), vec![])
+            .await
+            .unwrap();
+        let frame = canvas.get_frame();
+        for name in ["alpha", "beta", "gamma.txt", " mmm "] {
+            assert!(frame.contains(name), "{} missing from\n{}", name, frame);
+        }
+        assert!(frame.contains("frame"));
+    }
+
+    #[tokio::test]
+    async fn failed_navigation_is_shown_not_fatal() {
+        let root = make_tree("missing", &["alpha"], &[]);
+        let mut events = type_str("~/definitely-not-a-real-dir-for-mmm");
+        events.push(key(KeyCode::Enter));
+        let (app, canvas) = run_script(root.clone(), vec2!(60, 20).unwrap(), events)
+            .await
+            .unwrap();
+        assert_eq!(app.filesys.get_current_path(), *root);
+        assert!(canvas.get_frame().contains("cannot go to"));
+        // The input is left to be fixed
+        assert_eq!(app.filesys.get_mode(), MmmMode::GoTo);
+        assert_eq!(
+            app.filesys.get_filter(),
+            "~/definitely-not-a-real-dir-for-mmm"
+        );
+    }
+
+    #[tokio::test]
+    async fn returning_to_a_directory_restores_its_filter_and_selection() {
+        let root = make_tree("return", &["x/ant", "x/axe", "x/bee", "y"], &[]);
+        // Enter the second match, so the selection is not just the first entry again
+        let mut events = type_str("a");
+        events.push(key(KeyCode::Down));
+        events.push(key(KeyCode::Char(' ')));
+        let (entered, _) = run_script(root.join("x"), vec2!(60, 20).unwrap(), events.clone())
+            .await
+            .unwrap();
+        let left_through = entered.filesys.get_current_path().to_path_buf();
+        // Neither move is into or out of the other directory, so nothing picks the child
+        for dir in ["y", "x"] {
+            events.extend(type_str(&format!("{}/", root.join(dir).display())));
+            events.push(key(KeyCode::Enter));
+        }
+        let (app, _) = run_script(root.join("x"), vec2!(60, 20).unwrap(), events)
+            .await
+            .unwrap();
+        assert_eq!(app.filesys.get_current_path(), root.join("x"));
+        assert_eq!(app.filesys.get_filter(), "a");
+        assert_eq!(
+            app.filesys
+                .get_selected_entry()
+                .map(|entry| entry.get_path().to_path_buf()),
+            Some(left_through)
+        );
+    }
+
+    #[tokio::test]
+    async fn going_back_after_go_to_does_not_restore_the_input() {
+        let root = make_tree("go-to-back", &["alpha/inner"], &[]);
+        let mut events = type_str(&format!("{}/", root.join("alpha").display()));
+        events.push(key(KeyCode::Enter));
+        events.push(Event::Key(KeyEvent::new(KeyCode::Left, KeyModifiers::ALT)));
+        let data_home = make_data_home();
+        let app = MmmApp::new(
+            root.join("alpha/inner"),
+            vec2!(60, 20).unwrap(),
+            &MmmConfig::default(),
+            Some(&data_home),
+            None,
+            MmmStatus::default(),
+        )
+        .unwrap();
+        let (app, _) = run_app(app, events).await.unwrap();
+        assert_eq!(app.filesys.get_current_path(), root.join("alpha/inner"));
+        assert_eq!(app.filesys.get_mode(), MmmMode::Browse);
+        assert!(app.filesys.filter_is_empty());
+    }
+
+    #[tokio::test]
+    async fn bookmarks_can_be_renamed_to_names_the_filter_would_hide() {
+        let root = make_tree("rename-bookmark", &["alpha", "beta"], &[]);
+        let data_home = make_data_home();
+        fs::create_dir_all(data_home.join("mmm")).unwrap();
+        fs::write(
+            data_home.join("mmm/bookmarks"),
+            format!(
+                "a\talpha\t{}\nb\tbeta\t{}\n",
+                root.join("alpha").display(),
+                root.join("beta").display()
+            ),
+        )
+        .unwrap();
+        let app = MmmApp::new(
+            root.clone(),
+            vec2!(60, 20).unwrap(),
+            &MmmConfig::default(),
+            Some(&data_home),
+            None,
+            MmmStatus::default(),
+        )
+        .unwrap();
+        let ctrl = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL));
+        let mut events = vec![ctrl('g')];
+        events.extend(type_str("bet"));
+        events.push(ctrl('n'));
+        events.extend(vec![key(KeyCode::Backspace); 4]);
+        events.extend(type_str("zzz"));
+        events.push(key(KeyCode::Enter));
+        let (app, canvas) = run_app(app, events).await.unwrap();
+        assert!(canvas.get_frame().contains("b zzz"));
+        assert!(app.filesys.filter_is_empty());
+        assert_eq!(
+            fs::read_to_string(data_home.join("mmm/bookmarks")).unwrap(),
+            format!(
+                "a\talpha\t{}\nb\tzzz\t{}\n",
+                root.join("alpha").display(),
+                root.join("beta").display()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn a_stop_signal_ends_the_loop_before_pending_input() {
+        let root = make_tree("signal", &["alpha"], &[]);
+        let data_home = make_data_home();
+        let mut app = MmmApp::new(
+            root.clone(),
+            vec2!(60, 20).unwrap(),
+            &MmmConfig::default(),
+            Some(&data_home),
+            None,
+            MmmStatus::default(),
+        )
+        .unwrap();
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let signal = MmmSignal {
+            name: "SIGTERM",
+            number: 15,
+        };
+        sender.unbounded_send(signal).unwrap();
+        app.signals = Some(receiver);
+        let (app, _) = run_app(app, type_str("alp")).await.unwrap();
+        assert_eq!(app.stopped_by, Some(signal));
+        assert!(app.filesys.filter_is_empty());
+    }
+
+    #[tokio::test]
+    async fn too_small_a_terminal_is_a_fatal_error() {
+        let root = make_tree("too-small", &[], &[]);
+        let result = run_script(root.clone(), vec2!(9, 20).unwrap(), vec![]).await;
+        assert!(result.is_err_and(|err| err.is_fatal()));
+    }
+
+    #[tokio::test]
+    async fn inline_display_follows_the_terminal_size() {
+        let root = make_tree("inline", &["alpha", "beta", "gamma"], &[]);
+        let display = MmmDisplay::Inline {
+            height: 12,
+            origin: 18,
+        };
+        let data_home = make_data_home();
+        let mut app = MmmApp::new(
+            root.clone(),
+            display.fit(vec2!(60, 30).unwrap()),
+            &MmmConfig::default(),
+            Some(&data_home),
+            None,
+            MmmStatus::default(),
+        )
+        .unwrap();
+        app.layout.display = display;
+        // Shrinking the terminal pushes the display up so all of its rows are still on screen,
+        // then a click on the second row of the current pane has to allow for where it starts
+        let click = |row| {
+            Event::Mouse(MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column: 40,
+                row,
+                modifiers: KeyModifiers::NONE,
+            })
+        };
+        let events = vec![Event::Resize(60, 24), click(12 + 3 + 1), click(2)];
+        let (app, canvas) = run_app(app, events).await.unwrap();
+        assert_eq!(
+            app.layout.display,
+            MmmDisplay::Inline {
+                height: 12,
+                origin: 12
+            }
+        );
+        assert_eq!(app.layout.term_size, vec2!(60, 12).unwrap());
+        assert_eq!(canvas.get_frame().lines().count(), 12);
+        assert_eq!(get_selected_name(&app).as_deref(), Some("beta"));
+    }
+
+    #[tokio::test]
+    async fn enter_picks_the_selected_entry_and_stops() {
+        let root = make_tree("pick", &["alpha"], &["beta.txt"]);
+        let mut events = type_str("bet");
+        events.push(key(KeyCode::Enter));
+        events.extend(type_str("xyz"));
+        let (app, _) = run_picker(root.clone(), MmmPicker::default(), events).await;
+        assert_eq!(app.filesys.get_picked(), Some(&[root.join("beta.txt")][..]));
+        assert_eq!(app.filesys.get_filter(), "bet");
+    }
+
+    #[tokio::test]
+    async fn picking_files_enters_folders() {
+        let root = make_tree("pick-files", &["alpha"], &["alpha/one.txt"]);
+        let picker = MmmPicker {
+            kind: MmmPickKind::Files,
+            multi: false,
+        };
+        let events = vec![key(KeyCode::Enter), key(KeyCode::Enter)];
+        let (app, _) = run_picker(root.clone(), picker, events).await;
+        assert_eq!(
+            app.filesys.get_picked(),
+            Some(&[root.join("alpha/one.txt")][..])
+        );
+    }
+
+    #[tokio::test]
+    async fn picking_dirs_refuses_files() {
+        let root = make_tree("pick-dirs", &[], &["one.txt"]);
+        let picker = MmmPicker {
+            kind: MmmPickKind::Dirs,
+            multi: false,
+        };
+        let (app, canvas) = run_picker(root.clone(), picker, vec![key(KeyCode::Enter)]).await;
+        assert_eq!(app.filesys.get_picked(), None);
+        assert!(canvas.get_frame().contains("is not a directory"));
+    }
+
+    #[tokio::test]
+    async fn multi_picks_every_marked_entry() {
+        let root = make_tree("pick-multi", &[], &["one", "three", "two"]);
+        let ctrl_t = Event::Key(KeyEvent::new(KeyCode::Char('t'), KeyModifiers::CONTROL));
+        let events = vec![
+            ctrl_t.clone(),
+            key(KeyCode::Down),
+            key(KeyCode::Down),
+            ctrl_t,
+            key(KeyCode::Enter),
+        ];
+        let picker = MmmPicker {
+            kind: MmmPickKind::Any,
+            multi: true,
+        };
+        let (app, _) = run_picker(root.clone(), picker, events).await;
+        assert_eq!(
+            app.filesys.get_picked(),
+            Some(&[root.join("one"), root.join("two")][..])
+        );
+    }
+
+    #[tokio::test]
+    async fn on_select_runs_for_each_pick_and_carries_on() {
+        let root = make_tree("on-select", &[], &["one", "two"]);
+        let log = root.join("picked.log");
+        let data_home = make_data_home();
+        let mut app = MmmApp::new(
+            root.clone(),
+            vec2!(60, 20).unwrap(),
+            &MmmConfig::default(),
+            Some(&data_home),
+            Some(MmmPicker::default()),
+            MmmStatus::default(),
+        )
+        .unwrap();
+        app.on_select = Some(format!(
+            "printf '%s\\n' {{}} >> {}",
+            quote_path(&log).to_string_lossy()
+        ));
+        let mut events = type_str("one");
+        events.push(key(KeyCode::Enter));
+        events.push(key(KeyCode::Backspace));
+        events.extend(type_str("two"));
+        events.push(key(KeyCode::Enter));
+        let (app, _) = run_app(app, events).await.unwrap();
+        assert_eq!(app.filesys.get_picked(), None);
+        assert_eq!(app.get_on_select_picks(), 2);
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            format!(
+                "{}\n{}\n",
+                root.join("one").display(),
+                root.join("two").display()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn commands_are_filled_in_from_where_they_were_opened() {
+        let root = make_tree("commands", &[], &["archive.tar", "notes"]);
+        let config = MmmConfig::parse(
+            "[commands]\ngs = \"git status\"\nx = \"tar xf {} -C {dir}\" # unpack\n",
+        )
+        .unwrap();
+        let data_home = make_data_home();
+        let app = MmmApp::new(
+            root.clone(),
+            vec2!(60, 20).unwrap(),
+            &config,
+            Some(&data_home),
+            None,
+            MmmStatus::default(),
+        )
+        .unwrap();
+        let mut events = type_str("arch");
+        events.push(Event::Key(KeyEvent::new(
+            KeyCode::Char('k'),
+            KeyModifiers::CONTROL,
+        )));
+        events.extend(type_str("tar"));
+        let (app, canvas) = run_app(app, events).await.unwrap();
+        let frame = canvas.get_frame();
+        assert!(frame.contains("x tar xf {} -C {dir}"));
+        // The status line shows the command to run rather than details of a file
+        assert!(frame.contains("tar xf '"));
+        assert!(!frame.contains("rw"));
+        assert_eq!(
+            app.filesys.get_selected_command(),
+            Some((
+                "x".to_string(),
+                OsString::from(format!(
+                    "tar xf {} -C {}",
+                    quote_path(&root.join("archive.tar")).to_string_lossy(),
+                    quote_path(&root).to_string_lossy()
+                ))
+            ))
+        );
+    }
+}
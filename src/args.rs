@@ -0,0 +1,152 @@
+use std::path::PathBuf;
+
+use crate::{
+    error_type::MmmResult,
+    filesystem::picker::{MmmPickKind, MmmPicker},
+};
+
+pub const USAGE: &str = "\
+Usage: mmm [options]
+
+Options:
+  --height N    Draw in N rows below the prompt instead of taking over the screen
+  --pick        Print the paths picked with Enter instead of the current directory
+  --files       Only pick files, Enter goes into folders
+  --dirs        Only pick folders
+  --multi       Pick every entry marked with Ctrl-t
+  --print0      Separate picked paths with NUL instead of newlines
+
+  --chooser-file FILE  Write the picked paths to FILE instead of printing them, implies --pick
+  --cwd-file FILE      Write the final directory to FILE instead of /tmp/mmm.path
+  --on-select CMD      Run CMD with {} replaced by the picked paths each time Enter picks,
+                       carrying on until Esc instead of stopping, implies --pick
+  -h, --help    Show this message
+";
+
+/// The smallest inline display that still fits the panes
+const MIN_HEIGHT: u16 = 4;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MmmArgs {
+    pub height: Option<u16>,
+    pub picker: Option<MmmPicker>,
+    pub print0: bool,
+    pub chooser_file: Option<PathBuf>,
+    pub cwd_file: Option<PathBuf>,
+    pub on_select: Option<String>,
+    pub help: bool,
+}
+
+impl MmmArgs {
+    /// Parses the command line arguments, without the program name
+    pub fn parse(args: impl IntoIterator<Item = String>) -> MmmResult<Self> {
+        let mut parsed = MmmArgs::default();
+        let (mut pick, mut multi) = (false, false);
+        let mut kinds = vec![];
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or(format!("{} needs a value", name))
+            };
+            match name {
+                "--height" => parsed.height = Some(parse_height(&value()?)?),
+                "--pick" => pick = true,
+                "--files" => kinds.push(MmmPickKind::Files),
+                "--dirs" => kinds.push(MmmPickKind::Dirs),
+                "--multi" => multi = true,
+                "--print0" => parsed.print0 = true,
+                "--chooser-file" => parsed.chooser_file = Some(value()?.into()),
+                "--cwd-file" => parsed.cwd_file = Some(value()?.into()),
+                "--on-select" => parsed.on_select = Some(value()?),
+                "-h" | "--help" => parsed.help = true,
+                _ => return Err(format!("unknown option {}", arg).into()),
+            }
+        }
+        kinds.dedup();
+        if kinds.len() > 1 {
+            return Err("--files and --dirs cannot be used together".into());
+        }
+        if pick || parsed.chooser_file.is_some() || parsed.on_select.is_some() {
+            parsed.picker = Some(MmmPicker {
+                kind: kinds.pop().unwrap_or_default(),
+                multi,
+            });
+        } else if !kinds.is_empty() || multi || parsed.print0 {
+            return Err("--files, --dirs, --multi and --print0 need --pick".into());
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_height(value: &str) -> MmmResult<u16> {
+    match value.parse::<u16>() {
+        Ok(height) if height >= MIN_HEIGHT => Ok(height),
+        _ => Err(format!("--height must be a number of rows, at least {}", MIN_HEIGHT).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> MmmResult<MmmArgs> {
+        MmmArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn height_takes_a_separate_or_joined_value() {
+        assert_eq!(parse(&["--height", "12"]).unwrap().height, Some(12));
+        assert_eq!(parse(&["--height=12"]).unwrap().height, Some(12));
+        assert_eq!(parse(&[]).unwrap(), MmmArgs::default());
+    }
+
+    #[test]
+    fn bad_arguments_are_rejected() {
+        assert!(parse(&["--height"]).is_err());
+        assert!(parse(&["--height", "2"]).is_err());
+        assert!(parse(&["--height", "lots"]).is_err());
+        assert!(parse(&["--colour"]).is_err());
+        assert!(parse(&["--multi"]).is_err());
+        assert!(parse(&["--pick", "--files", "--dirs"]).is_err());
+        assert!(parse(&["--cwd-file"]).is_err());
+    }
+
+    #[test]
+    fn pick_options_build_a_picker() {
+        let args = parse(&["--files", "--pick", "--multi", "--print0"]).unwrap();
+        assert_eq!(
+            args.picker,
+            Some(MmmPicker {
+                kind: MmmPickKind::Files,
+                multi: true,
+            })
+        );
+        assert!(args.print0);
+        assert_eq!(
+            parse(&["--pick"]).unwrap().picker,
+            Some(MmmPicker::default())
+        );
+    }
+
+    #[test]
+    fn chooser_options_imply_pick() {
+        let args = parse(&["--chooser-file", "/tmp/chosen", "--cwd-file=/tmp/cwd"]).unwrap();
+        assert_eq!(args.picker, Some(MmmPicker::default()));
+        assert_eq!(args.chooser_file, Some(PathBuf::from("/tmp/chosen")));
+        assert_eq!(args.cwd_file, Some(PathBuf::from("/tmp/cwd")));
+        let args = parse(&["--on-select", "echo {}", "--dirs"]).unwrap();
+        assert_eq!(args.on_select.as_deref(), Some("echo {}"));
+        assert_eq!(
+            args.picker.map(|picker| picker.kind),
+            Some(MmmPickKind::Dirs)
+        );
+        assert_eq!(parse(&["--cwd-file", "/tmp/cwd"]).unwrap().picker, None);
+    }
+}
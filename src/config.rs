@@ -0,0 +1,230 @@
+use std::{fs, path::Path};
+
+use crate::error_type::MmmResult;
+
+/// User settings read from `$XDG_CONFIG_HOME/mmm/config.toml`, a small subset of TOML with
+/// `key = value` pairs, `[section]` headers and `#` comments
+#[derive(Clone, Debug, PartialEq)]
+pub struct MmmConfig {
+    pub remember_selection: bool,
+    pub physical_paths: bool,
+    pub auto_enter: bool,
+    /// `(name, command)` pairs from the `[commands]` section, in the order they were written
+    pub commands: Vec<(String, String)>,
+}
+
+impl Default for MmmConfig {
+    fn default() -> Self {
+        MmmConfig {
+            remember_selection: true,
+            physical_paths: false,
+            auto_enter: false,
+            commands: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum MmmConfigValue {
+    Bool(bool),
+    String(String),
+}
+
+impl MmmConfig {
+    /// Loads `mmm/config.toml` from the config directory, a missing file gives the defaults
+    pub fn load(config_home: &Path) -> MmmResult<Self> {
+        let file_path = config_home.join("mmm/config.toml");
+        match fs::read_to_string(&file_path) {
+            Ok(contents) => Self::parse(&contents)
+                .map_err(|err| format!("{}: {}", file_path.display(), err).into()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn parse(contents: &str) -> MmmResult<Self> {
+        let mut config = Self::default();
+        let mut section = String::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_string();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(format!("line {}: expected key = value", i + 1))?;
+            let key = key.trim();
+            let value =
+                parse_value(value.trim()).ok_or(format!("line {}: invalid value", i + 1))?;
+            config
+                .set(&section, key, value)
+                .map_err(|err| format!("line {}: {}", i + 1, err))?;
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, section: &str, key: &str, value: MmmConfigValue) -> Result<(), String> {
+        match (section, key) {
+            ("", "remember_selection") => self.remember_selection = value.as_bool(key)?,
+            ("", "physical_paths") => self.physical_paths = value.as_bool(key)?,
+            ("", "auto_enter") => self.auto_enter = value.as_bool(key)?,
+            ("commands", name) => {
+                if name.contains(char::is_whitespace) {
+                    return Err(format!("command name '{}' cannot contain spaces", name));
+                }
+                let command = value.as_string(name)?;
+                self.commands.retain(|(existing, _)| existing != name);
+                self.commands.push((name.to_string(), command));
+            }
+            ("", key) => return Err(format!("unknown setting '{}'", key)),
+            (section, key) => return Err(format!("unknown setting '{}.{}'", section, key)),
+        }
+        Ok(())
+    }
+}
+
+impl MmmConfigValue {
+    fn as_bool(&self, key: &str) -> Result<bool, String> {
+        match self {
+            MmmConfigValue::Bool(b) => Ok(*b),
+            _ => Err(format!("'{}' must be true or false", key)),
+        }
+    }
+
+    fn as_string(&self, key: &str) -> Result<String, String> {
+        match self {
+            MmmConfigValue::String(s) => Ok(s.clone()),
+            _ => Err(format!("'{}' must be a quoted string", key)),
+        }
+    }
+}
+
+/// Parses a `true`/`false` or double quoted string value, trailing comments are allowed
+fn parse_value(value: &str) -> Option<MmmConfigValue> {
+    if let Some(quoted) = value.strip_prefix('"') {
+        let mut string = String::new();
+        let mut chars = quoted.chars();
+        loop {
+            match chars.next()? {
+                '"' => break,
+                '\\' => match chars.next()? {
+                    'n' => string.push('\n'),
+                    't' => string.push('\t'),
+                    c => string.push(c),
+                },
+                c => string.push(c),
+            }
+        }
+        let rest = chars.as_str().trim();
+        if rest.is_empty() || rest.starts_with('#') {
+            return Some(MmmConfigValue::String(string));
+        }
+        return None;
+    }
+    match value.split('#').next()?.trim() {
+        "true" => Some(MmmConfigValue::Bool(true)),
+        "false" => Some(MmmConfigValue::Bool(false)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(contents: &str) -> String {
+        MmmConfig::parse(contents).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn switches_are_read_with_comments_and_blank_lines() {
+        let config = MmmConfig::parse(
+            "# settings\n\n  # indented\nauto_enter = true # trailing\nphysical_paths=true\n",
+        )
+        .unwrap();
+        assert!(config.auto_enter);
+        assert!(config.physical_paths);
+        assert!(config.remember_selection);
+        assert_eq!(MmmConfig::parse("").unwrap(), MmmConfig::default());
+    }
+
+    #[test]
+    fn a_repeated_switch_keeps_the_last_value() {
+        let config = MmmConfig::parse("auto_enter = true\nauto_enter = false\n").unwrap();
+        assert!(!config.auto_enter);
+    }
+
+    #[test]
+    fn bad_switches_are_rejected() {
+        assert_eq!(
+            parse_error("colour = true"),
+            "line 1: unknown setting 'colour'"
+        );
+        assert_eq!(parse_error("\nauto_enter"), "line 2: expected key = value");
+        assert_eq!(parse_error("auto_enter = yes"), "line 1: invalid value");
+        assert_eq!(
+            parse_error("auto_enter = \"true\""),
+            "line 1: 'auto_enter' must be true or false"
+        );
+    }
+
+    #[test]
+    fn commands_are_quoted_strings_with_escapes() {
+        let config = MmmConfig::parse(concat!(
+            "[commands]\n",
+            r#"hash = "echo '#' {}" # not this"#,
+            "\n",
+            r#"quote = "printf \"%s\\n\" a=b\tc""#,
+            "\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            config.commands,
+            vec![
+                ("hash".to_string(), "echo '#' {}".to_string()),
+                ("quote".to_string(), "printf \"%s\\n\" a=b\tc".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_repeated_command_moves_to_the_end_with_its_last_value() {
+        let config =
+            MmmConfig::parse("[commands]\na = \"one\"\nb = \"two\"\na = \"three\"\n").unwrap();
+        assert_eq!(
+            config.commands,
+            vec![
+                ("b".to_string(), "two".to_string()),
+                ("a".to_string(), "three".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_sections_and_commands_are_rejected() {
+        assert_eq!(
+            parse_error("\n[keys]\nopen = \"o\""),
+            "line 3: unknown setting 'keys.open'"
+        );
+        assert_eq!(
+            parse_error("[commands]\nauto_enter = true"),
+            "line 2: 'auto_enter' must be a quoted string"
+        );
+        assert_eq!(
+            parse_error("[commands]\nopen = \"xdg-open {}"),
+            "line 2: invalid value"
+        );
+        assert_eq!(
+            parse_error("[commands]\nopen = \"xdg-open\" {}"),
+            "line 2: invalid value"
+        );
+        assert_eq!(
+            parse_error("[commands]\nmy open = \"xdg-open {}\""),
+            "line 2: command name 'my open' cannot contain spaces"
+        );
+    }
+}
@@ -4,6 +4,8 @@
 pub enum MmmError {
     General(String),
     Io(std::io::Error),
+    Journal(String),
+    Terminal(String),
 }
 
 impl std::fmt::Display for MmmError {
@@ -11,12 +13,22 @@ impl std::fmt::Display for MmmError {
         match self {
             MmmError::General(s) => write!(f, "{}", s),
             MmmError::Io(err) => write!(f, "i/o error: {}", err),
+            MmmError::Journal(s) => write!(f, "journal error: {}", s),
+            MmmError::Terminal(s) => write!(f, "terminal error: {}", s),
         }
     }
 }
 
 impl std::error::Error for MmmError {}
 
+impl MmmError {
+    /// Whether the error leaves the terminal unusable, anything else can be shown to the user and
+    /// the program carries on
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, MmmError::Terminal(_))
+    }
+}
+
 impl From<std::io::Error> for MmmError {
     fn from(error: std::io::Error) -> Self {
         MmmError::Io(error)
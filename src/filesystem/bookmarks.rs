@@ -0,0 +1,112 @@
+use super::{
+    data_file::{get_storable_path, MmmDataFile},
+    MmmDirEntry,
+};
+use crate::error_type::MmmResult;
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MmmBookmark {
+    pub key: char,
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Bookmarked directories, persisted to `$XDG_DATA_HOME/mmm/bookmarks` as one tab separated
+/// `key name path` line per bookmark
+#[derive(Debug, Default)]
+pub struct MmmBookmarks {
+    bookmarks: Vec<MmmBookmark>,
+    file: MmmDataFile,
+}
+
+impl MmmBookmarks {
+    /// Loads the saved bookmarks, a missing or unreadable file gives an empty set
+    pub fn load(data_home: Option<&Path>) -> Self {
+        let file = MmmDataFile::new(data_home, "bookmarks");
+        MmmBookmarks {
+            bookmarks: file.read(parse_line),
+            file,
+        }
+    }
+
+    fn save(&self) -> MmmResult<()> {
+        self.file
+            .write(self.bookmarks.iter().filter_map(|bookmark| {
+                let path = get_storable_path(&bookmark.path)?;
+                Some(format!("{}\t{}\t{}", bookmark.key, bookmark.name, path))
+            }))
+    }
+
+    pub fn get(&self, key: char) -> Option<&MmmBookmark> {
+        self.bookmarks.iter().find(|bookmark| bookmark.key == key)
+    }
+
+    /// Bookmarks a path under `key`, replacing any bookmark already using that key
+    pub fn set(&mut self, key: char, path: &Path) -> MmmResult<()> {
+        let path_str = match get_storable_path(path) {
+            Some(path_str) if !key.is_control() && key != '\t' => path_str,
+            _ => return Err(format!("cannot bookmark {} under '{}'", path.display(), key).into()),
+        };
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or(path_str.to_string());
+        self.bookmarks.retain(|bookmark| bookmark.key != key);
+        self.bookmarks.push(MmmBookmark {
+            key,
+            name,
+            path: path.to_path_buf(),
+        });
+        self.bookmarks.sort_by_key(|bookmark| bookmark.key);
+        self.save()
+    }
+
+    pub fn remove(&mut self, key: char) -> MmmResult<()> {
+        self.bookmarks.retain(|bookmark| bookmark.key != key);
+        self.save()
+    }
+
+    pub fn rename(&mut self, key: char, name: &str) -> MmmResult<()> {
+        if name.is_empty() || name.contains(['\t', '\n']) {
+            return Err(format!("invalid bookmark name '{}'", name).into());
+        }
+        let bookmark = self
+            .bookmarks
+            .iter_mut()
+            .find(|bookmark| bookmark.key == key)
+            .ok_or(format!("no bookmark under '{}'", key))?;
+        bookmark.name = name.to_string();
+        self.save()
+    }
+
+    /// The bookmarks as directory entries named `key name`, for listing in the current pane
+    pub fn get_entries(&self) -> Vec<Rc<MmmDirEntry>> {
+        self.bookmarks
+            .iter()
+            .map(|bookmark| {
+                Rc::new(MmmDirEntry::Directory {
+                    name: format!("{} {}", bookmark.key, bookmark.name),
+                    path: bookmark.path.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+fn parse_line(line: &str) -> Option<MmmBookmark> {
+    let mut fields = line.splitn(3, '\t');
+    let mut key_chars = fields.next()?.chars();
+    let key = key_chars.next()?;
+    if key_chars.next().is_some() {
+        return None;
+    }
+    Some(MmmBookmark {
+        key,
+        name: fields.next()?.to_string(),
+        path: PathBuf::from(fields.next()?),
+    })
+}
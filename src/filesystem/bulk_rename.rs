@@ -0,0 +1,204 @@
+use crate::error_type::MmmResult;
+use std::{
+    collections::HashSet,
+    fs::{self, DirBuilder, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Writes the names to a temp file, one per line, and returns the file contents once the user's
+/// editor exits. The display must be stopped before calling this.
+pub fn edit_names_in_editor(names: &[String]) -> MmmResult<String> {
+    if let Some(name) = names.iter().find(|name| name.contains('\n')) {
+        return Err(format!("cannot bulk rename '{}', it contains a newline", name).into());
+    }
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or("vi".to_string());
+    let mut editor_args = editor.split_whitespace();
+    let editor_cmd = editor_args.next().ok_or("$EDITOR is empty")?;
+    let temp_dir = make_private_dir()?;
+    let temp_path = temp_dir.join("rename.txt");
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .and_then(|mut file| file.write_all((names.join("\n") + "\n").as_bytes()));
+    if let Err(err) = written {
+        let _ = fs::remove_dir_all(&temp_dir);
+        return Err(err.into());
+    }
+    let status = Command::new(editor_cmd)
+        .args(editor_args)
+        .arg(&temp_path)
+        .status();
+    let edited = fs::read_to_string(&temp_path);
+    let _ = fs::remove_dir_all(&temp_dir);
+    if !status?.success() {
+        return Err(format!("{} exited with an error, nothing was renamed", editor_cmd).into());
+    }
+    Ok(edited?)
+}
+
+/// Creates a new directory in the temp directory only the user can read, so the names being
+/// edited can't be read or swapped out by anyone else. An existing directory is never reused.
+fn make_private_dir() -> MmmResult<PathBuf> {
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    let base = std::env::temp_dir();
+    for i in 0..100 {
+        let dir = base.join(format!("mmm-rename-{}-{}", std::process::id(), i));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Err("cannot create a temporary directory for renaming".into())
+}
+
+/// Pairs the original names with the edited ones, returning only the names that changed.
+/// Nothing on disk is touched, so any error here leaves the directory as it was.
+pub fn plan_renames(
+    dir: &Path,
+    originals: &[String],
+    edited: &str,
+) -> MmmResult<Vec<(String, String)>> {
+    let mut new_names: Vec<&str> = edited.lines().collect();
+    while new_names.last() == Some(&"") {
+        new_names.pop();
+    }
+    if new_names.len() != originals.len() {
+        return Err(format!(
+            "expected {} names but found {}, nothing was renamed",
+            originals.len(),
+            new_names.len()
+        )
+        .into());
+    }
+    let mut seen = HashSet::new();
+    for name in &new_names {
+        if name.is_empty() || *name == "." || *name == ".." || name.contains('/') {
+            return Err(format!("invalid name '{}', nothing was renamed", name).into());
+        }
+        if !seen.insert(*name) {
+            return Err(format!("'{}' is used more than once, nothing was renamed", name).into());
+        }
+    }
+    let sources: HashSet<&str> = originals.iter().map(|name| name.as_str()).collect();
+    let renames: Vec<(String, String)> = originals
+        .iter()
+        .zip(new_names)
+        .filter(|(from, to)| from.as_str() != *to)
+        .map(|(from, to)| (from.clone(), to.to_string()))
+        .collect();
+    for (_, to) in &renames {
+        if !sources.contains(to.as_str()) && fs::symlink_metadata(dir.join(to)).is_ok() {
+            return Err(format!("'{}' already exists, nothing was renamed", to).into());
+        }
+    }
+    Ok(renames)
+}
+
+/// Orders renames so no target is overwritten before it has been moved out of the way. Chains
+/// are renamed from the end, and cycles such as a->b, b->a are broken with a temporary name.
+pub fn order_renames(dir: &Path, renames: Vec<(String, String)>) -> Vec<(String, String)> {
+    let mut pending = renames;
+    let mut steps = vec![];
+    while !pending.is_empty() {
+        let ready = pending
+            .iter()
+            .position(|(_, to)| !pending.iter().any(|(from, _)| from == to));
+        if let Some(idx) = ready {
+            steps.push(pending.remove(idx));
+        } else {
+            // Everything left is part of a cycle, park one entry under a temporary name
+            let (from, to) = pending.remove(0);
+            let temp = (0..)
+                .map(|i| format!(".mmm-rename-{}-{}", i, from))
+                .find(|temp| {
+                    !pending.iter().any(|(a, b)| a == temp || b == temp)
+                        && fs::symlink_metadata(dir.join(temp)).is_err()
+                })
+                .expect("unbounded iterator ended");
+            steps.push((from, temp.clone()));
+            pending.push((temp, to));
+        }
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::make_tree;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn only_changed_names_are_planned() {
+        let dir = make_tree("rename-plan", &[], &["a", "b", "c"]);
+        let plan = plan_renames(&dir, &names(&["a", "b", "c"]), "a\nB\nc\n\n").unwrap();
+        assert_eq!(plan, pairs(&[("b", "B")]));
+    }
+
+    #[test]
+    fn a_different_number_of_names_is_refused() {
+        let dir = make_tree("rename-count", &[], &["a", "b"]);
+        assert!(plan_renames(&dir, &names(&["a", "b"]), "a\n").is_err());
+        assert!(plan_renames(&dir, &names(&["a", "b"]), "a\nb\nc\n").is_err());
+    }
+
+    #[test]
+    fn duplicate_and_invalid_targets_are_refused() {
+        let dir = make_tree("rename-duplicate", &[], &["a", "b"]);
+        let originals = names(&["a", "b"]);
+        assert!(plan_renames(&dir, &originals, "c\nc\n").is_err());
+        assert!(plan_renames(&dir, &originals, "a\n\n").is_err());
+        assert!(plan_renames(&dir, &originals, "a\nsub/b\n").is_err());
+        assert!(plan_renames(&dir, &originals, "a\n..\n").is_err());
+    }
+
+    #[test]
+    fn renaming_onto_an_entry_not_being_renamed_is_refused() {
+        let dir = make_tree("rename-clash", &[], &["a", "b", "other"]);
+        assert!(plan_renames(&dir, &names(&["a", "b"]), "other\nb\n").is_err());
+        // Taking a name another renamed entry is giving up is fine
+        assert_eq!(
+            plan_renames(&dir, &names(&["a", "b"]), "b\nc\n").unwrap(),
+            pairs(&[("a", "b"), ("b", "c")])
+        );
+    }
+
+    #[test]
+    fn chains_are_renamed_from_the_end() {
+        let dir = make_tree("rename-chain", &[], &["a", "b"]);
+        let steps = order_renames(&dir, pairs(&[("a", "b"), ("b", "c")]));
+        assert_eq!(steps, pairs(&[("b", "c"), ("a", "b")]));
+    }
+
+    #[test]
+    fn swaps_go_through_a_temporary_name() {
+        let dir = make_tree("rename-swap", &[], &["a", "b", ".mmm-rename-0-a"]);
+        let steps = order_renames(&dir, pairs(&[("a", "b"), ("b", "a")]));
+        assert_eq!(
+            steps,
+            pairs(&[
+                ("a", ".mmm-rename-1-a"),
+                ("b", "a"),
+                (".mmm-rename-1-a", "b")
+            ])
+        );
+    }
+}
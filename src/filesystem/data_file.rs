@@ -0,0 +1,57 @@
+use crate::error_type::MmmResult;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A file of one record per line kept under `$XDG_DATA_HOME/mmm`, as bookmarks and history are
+#[derive(Debug, Default)]
+pub struct MmmDataFile {
+    name: &'static str,
+    file_path: Option<PathBuf>,
+}
+
+impl MmmDataFile {
+    /// The file called `name` in the data directory, there is no file without a data directory
+    pub fn new(data_home: Option<&Path>, name: &'static str) -> Self {
+        MmmDataFile {
+            name,
+            file_path: data_home.map(|data_home| data_home.join("mmm").join(name)),
+        }
+    }
+
+    /// Parses each line, skipping lines that don't parse. A missing or unreadable file is empty.
+    pub fn read<T>(&self, parse: impl Fn(&str) -> Option<T>) -> Vec<T> {
+        self.file_path
+            .as_ref()
+            .and_then(|file_path| fs::read_to_string(file_path).ok())
+            .map(|contents| contents.lines().filter_map(parse).collect())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the file with the lines, each followed by a newline. The lines go to a temporary
+    /// file first that is renamed over the old one, so a reader never sees half a file.
+    pub fn write(&self, lines: impl Iterator<Item = String>) -> MmmResult<()> {
+        let file_path = self
+            .file_path
+            .as_ref()
+            .ok_or(format!("cannot save {}, no data directory", self.name))?;
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents: String = lines.map(|line| line + "\n").collect();
+        let temp_path = file_path.with_file_name(format!(".{}.{}", self.name, std::process::id()));
+        let result =
+            fs::write(&temp_path, contents).and_then(|_| fs::rename(&temp_path, file_path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        Ok(result?)
+    }
+}
+
+/// A path as it is written in a data file, if it can be. Paths that aren't valid UTF-8 or that
+/// contain a tab or newline would not read back as the same path.
+pub fn get_storable_path(path: &Path) -> Option<&str> {
+    path.to_str().filter(|path| !path.contains(['\t', '\n']))
+}
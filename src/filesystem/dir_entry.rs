@@ -1,6 +1,31 @@
-use super::MmmDirEntry;
+use super::{MmmDirEntry, MmmLinkTarget};
 use crate::error_type::MmmResult;
-use std::{fs, path::Path, rc::Rc};
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The directory mmm was started in. The kernel only knows the physical path, so unless
+/// `physical` is set `$PWD` is used when it is an absolute path to the same directory, as the
+/// shell does for `pwd -L`
+pub fn get_working_dir(physical: bool) -> MmmResult<PathBuf> {
+    let current_dir = std::env::current_dir()?;
+    if physical {
+        return Ok(current_dir);
+    }
+    let logical_dir = std::env::var_os("PWD")
+        .map(PathBuf::from)
+        .filter(|pwd| {
+            pwd.is_absolute()
+                && pwd
+                    .components()
+                    .all(|c| !matches!(c, Component::CurDir | Component::ParentDir))
+        })
+        .filter(|pwd| fs::canonicalize(pwd).is_ok_and(|pwd| pwd == current_dir));
+    Ok(logical_dir.unwrap_or(current_dir))
+}
 
 #[allow(unused)]
 pub fn get_path_size(path: &Path) -> MmmResult<u64> {
@@ -17,6 +42,86 @@ pub fn get_path_size(path: &Path) -> MmmResult<u64> {
     Ok(total_size)
 }
 
+/// Formats a byte count with binary prefixes, e.g. `1.5M`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "K", "M", "G", "T", "P"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[0])
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+/// Formats a time as `YYYY-MM-DD hh:mm:ss` in UTC, with `separator` between the date and time
+pub fn format_time(time: SystemTime, separator: char) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // Civil from days, see Howard Hinnant's date algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}{}{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        separator,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// Formats permissions `ls -l` style, e.g. `drwxr-xr-x`
+pub fn format_permissions(metadata: &fs::Metadata) -> String {
+    let kind = if metadata.file_type().is_symlink() {
+        'l'
+    } else if metadata.is_dir() {
+        'd'
+    } else {
+        '-'
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = metadata.permissions().mode();
+        std::iter::once(kind)
+            .chain((0..9).map(|i| {
+                if mode & (0o400 >> i) == 0 {
+                    '-'
+                } else {
+                    ['r', 'w', 'x'][i % 3]
+                }
+            }))
+            .collect()
+    }
+    #[cfg(not(unix))]
+    {
+        let write = if metadata.permissions().readonly() {
+            '-'
+        } else {
+            'w'
+        };
+        format!("{}r{}", kind, write)
+    }
+}
+
 pub fn get_dir_list(path: &Path) -> MmmResult<Vec<Rc<MmmDirEntry>>> {
     let mut dir_list_not_folders = vec![];
     let mut dir_list_base = vec![];
@@ -46,11 +151,23 @@ pub fn get_dir_list(path: &Path) -> MmmResult<Vec<Rc<MmmDirEntry>>> {
                     path: entry.path(),
                 }));
             } else if file_type.is_symlink() {
-                dir_list_not_folders.push(Rc::new(MmmDirEntry::Link {
+                let target = match fs::metadata(entry.path()) {
+                    Ok(metadata) if metadata.is_dir() => MmmLinkTarget::Directory,
+                    Ok(_) => MmmLinkTarget::File,
+                    Err(_) => MmmLinkTarget::Broken,
+                };
+                let link = Rc::new(MmmDirEntry::Link {
                     name: entry.file_name().to_string_lossy().to_string(),
                     path: entry.path(),
                     linked_path: fs::read_link(entry.path()).ok(),
-                }));
+                    target,
+                });
+                // Links to directories are listed with the directories
+                if target == MmmLinkTarget::Directory {
+                    dir_list_base.push(link);
+                } else {
+                    dir_list_not_folders.push(link);
+                }
             } else {
                 dir_list_not_folders.push(Rc::new(MmmDirEntry::Other {
                     name: entry.file_name().to_string_lossy().to_string(),
@@ -1,10 +1,10 @@
 use super::{MmmDirEntry, MmmScoredDirEntry};
-use std::rc::Rc;
+use std::{path::Path, rc::Rc};
 
 pub fn filter_and_score(entry: Rc<MmmDirEntry>, filter: &str) -> Option<MmmScoredDirEntry> {
     filter_match(entry.get_name(), filter).map(|filter_match| {
         let mut score: i32 = 0;
-        if let MmmDirEntry::Directory { name: _, path: _ } = *entry {
+        if entry.is_dir_like() {
             score += 1000000
         }
         score += evaluate_score(&filter_match);
@@ -75,12 +75,9 @@ pub fn filter_hidden(entry: Rc<MmmDirEntry>, allow_hidden: bool) -> Option<Rc<Mm
 pub fn filter_hidden_with_exception(
     entry: Rc<MmmDirEntry>,
     allow_hidden: bool,
-    exception_name: &str,
+    exception: &Path,
 ) -> Option<Rc<MmmDirEntry>> {
-    if !allow_hidden
-        && entry.get_name().chars().next()? == '.'
-        && entry.get_name() != exception_name
-    {
+    if !allow_hidden && entry.get_name().starts_with('.') && entry.get_path() != exception {
         return None;
     }
     Some(entry)
@@ -0,0 +1,317 @@
+use super::{
+    data_file::{get_storable_path, MmmDataFile},
+    MmmDirEntry,
+};
+use crate::error_type::MmmResult;
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+/// Once the ranks add up to this much they are all aged, so stale directories drop out
+const MAX_TOTAL_RANK: f64 = 10000.0;
+/// How many visits are kept in memory before the history is saved
+const SAVE_EVERY: usize = 20;
+
+#[derive(Clone, Debug, PartialEq)]
+struct MmmFrecencyEntry {
+    path: PathBuf,
+    rank: f64,
+    last_access: u64,
+}
+
+impl MmmFrecencyEntry {
+    /// Weights the visit count by how recently the directory was visited, as zoxide does
+    fn get_score(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_access);
+        let weight = if age < HOUR {
+            4.0
+        } else if age < DAY {
+            2.0
+        } else if age < WEEK {
+            0.5
+        } else {
+            0.25
+        };
+        self.rank * weight
+    }
+}
+
+/// Every directory visited in mmm ranked by frequency and recency, persisted to
+/// `$XDG_DATA_HOME/mmm/frecency` as one tab separated `rank last_access path` line per entry.
+/// Visits are saved in batches and when mmm exits rather than on every directory change.
+#[derive(Debug, Default)]
+pub struct MmmFrecency {
+    entries: Vec<MmmFrecencyEntry>,
+    file: MmmDataFile,
+    /// Visits since the last save, as `(path, time)`
+    unsaved: Vec<(PathBuf, u64)>,
+}
+
+impl MmmFrecency {
+    /// Loads the saved history, a missing or unreadable file gives an empty history
+    pub fn load(data_home: Option<&Path>) -> Self {
+        let file = MmmDataFile::new(data_home, "frecency");
+        MmmFrecency {
+            entries: file.read(parse_line),
+            file,
+            unsaved: Vec::new(),
+        }
+    }
+
+    /// Writes out any visits recorded since the last save. They are added to what is saved now
+    /// rather than what was loaded, so visits saved by another mmm in the meantime are kept.
+    pub fn save(&mut self) -> MmmResult<()> {
+        if self.unsaved.is_empty() {
+            return Ok(());
+        }
+        let unsaved = std::mem::take(&mut self.unsaved);
+        self.entries = self.file.read(parse_line);
+        for (path, now) in &unsaved {
+            self.add_visit(path, *now);
+        }
+        let result = self.file.write(self.entries.iter().filter_map(|entry| {
+            let path = get_storable_path(&entry.path)?;
+            Some(format!("{}\t{}\t{}", entry.rank, entry.last_access, path))
+        }));
+        // Kept to try again, they are added to the file as it is then
+        if result.is_err() {
+            self.unsaved = unsaved;
+        }
+        result
+    }
+
+    /// Records a visit to a directory. History is a convenience, so failing to save it is not
+    /// worth interrupting navigation for.
+    pub fn record(&mut self, path: &Path) {
+        if get_storable_path(path).is_none() {
+            return;
+        }
+        let now = get_now();
+        self.add_visit(path, now);
+        self.unsaved.push((path.to_path_buf(), now));
+        if self.unsaved.len() >= SAVE_EVERY {
+            let _ = self.save();
+        }
+    }
+
+    fn add_visit(&mut self, path: &Path, now: u64) {
+        match self.entries.iter_mut().find(|entry| entry.path == path) {
+            Some(entry) => {
+                entry.rank += 1.0;
+                entry.last_access = now;
+            }
+            None => self.entries.push(MmmFrecencyEntry {
+                path: path.to_path_buf(),
+                rank: 1.0,
+                last_access: now,
+            }),
+        }
+        if self.entries.iter().map(|entry| entry.rank).sum::<f64>() > MAX_TOTAL_RANK {
+            self.entries.iter_mut().for_each(|entry| entry.rank *= 0.9);
+            self.entries.retain(|entry| entry.rank >= 1.0);
+        }
+    }
+
+    /// A bonus added to a fuzzy match score so frequently and recently used directories win
+    pub fn get_score_bonus(&self, path: &Path) -> i32 {
+        let now = get_now();
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path)
+            .map(|entry| ((1.0 + entry.get_score(now)).ln() * 200.0) as i32)
+            .unwrap_or(0)
+    }
+
+    /// The directories that still exist as entries named by their full path, best first. This
+    /// checks every directory, so it is worth keeping the result.
+    pub fn get_entries(&self) -> Vec<Rc<MmmDirEntry>> {
+        let now = get_now();
+        let mut entries: Vec<&MmmFrecencyEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.path.is_dir())
+            .collect();
+        entries.sort_by(|a, b| b.get_score(now).total_cmp(&a.get_score(now)));
+        entries
+            .into_iter()
+            .map(|entry| {
+                Rc::new(MmmDirEntry::Directory {
+                    name: entry.path.to_string_lossy().to_string(),
+                    path: entry.path.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+fn get_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn parse_line(line: &str) -> Option<MmmFrecencyEntry> {
+    let mut fields = line.splitn(3, '\t');
+    Some(MmmFrecencyEntry {
+        rank: fields.next()?.parse().ok()?,
+        last_access: fields.next()?.parse().ok()?,
+        path: PathBuf::from(fields.next()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{make_data_home, make_tree};
+
+    fn entry(rank: f64, last_access: u64) -> MmmFrecencyEntry {
+        MmmFrecencyEntry {
+            path: PathBuf::from("/d"),
+            rank,
+            last_access,
+        }
+    }
+
+    #[test]
+    fn recent_visits_weigh_more() {
+        let now = 10 * WEEK;
+        assert_eq!(entry(2.0, now).get_score(now), 8.0);
+        assert_eq!(entry(2.0, now - HOUR + 1).get_score(now), 8.0);
+        assert_eq!(entry(2.0, now - HOUR).get_score(now), 4.0);
+        assert_eq!(entry(2.0, now - DAY).get_score(now), 1.0);
+        assert_eq!(entry(2.0, now - WEEK).get_score(now), 0.5);
+        // A clock that went backwards counts as a visit just now
+        assert_eq!(entry(2.0, now + DAY).get_score(now), 8.0);
+    }
+
+    #[test]
+    fn visits_add_to_the_rank() {
+        let mut frecency = MmmFrecency::default();
+        frecency.add_visit(Path::new("/a"), 100);
+        frecency.add_visit(Path::new("/b"), 100);
+        frecency.add_visit(Path::new("/a"), 200);
+        assert_eq!(
+            frecency.entries,
+            vec![
+                MmmFrecencyEntry {
+                    path: PathBuf::from("/a"),
+                    rank: 2.0,
+                    last_access: 200,
+                },
+                MmmFrecencyEntry {
+                    path: PathBuf::from("/b"),
+                    rank: 1.0,
+                    last_access: 100,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ranks_are_aged_once_they_add_up_too_high() {
+        let mut frecency = MmmFrecency {
+            entries: vec![
+                MmmFrecencyEntry {
+                    path: PathBuf::from("/busy"),
+                    rank: MAX_TOTAL_RANK - 1.0,
+                    last_access: 0,
+                },
+                MmmFrecencyEntry {
+                    path: PathBuf::from("/rare"),
+                    rank: 1.0,
+                    last_access: 0,
+                },
+            ],
+            ..MmmFrecency::default()
+        };
+        frecency.add_visit(Path::new("/busy"), 0);
+        assert_eq!(frecency.entries.len(), 1);
+        assert_eq!(frecency.entries[0].rank, MAX_TOTAL_RANK * 0.9);
+    }
+
+    #[test]
+    fn entries_are_best_first_and_only_existing_dirs() {
+        let root = make_tree("frecency", &["often", "once"], &[]);
+        let mut frecency = MmmFrecency::default();
+        frecency.record(&root.join("once"));
+        frecency.record(&root.join("gone"));
+        frecency.record(&root.join("often"));
+        frecency.record(&root.join("often"));
+        let paths: Vec<PathBuf> = frecency
+            .get_entries()
+            .iter()
+            .map(|entry| entry.get_path().to_path_buf())
+            .collect();
+        assert_eq!(paths, vec![root.join("often"), root.join("once")]);
+        assert!(
+            frecency.get_score_bonus(&root.join("often"))
+                > frecency.get_score_bonus(&root.join("once"))
+        );
+        assert_eq!(frecency.get_score_bonus(&root.join("never")), 0);
+    }
+
+    #[test]
+    fn visits_are_saved_in_batches() {
+        let data_home = make_data_home();
+        let file_path = data_home.join("mmm/frecency");
+        let mut frecency = MmmFrecency::load(Some(&data_home));
+        for _ in 0..SAVE_EVERY - 1 {
+            frecency.record(Path::new("/a"));
+        }
+        assert!(!file_path.exists());
+        frecency.record(Path::new("/a"));
+        assert!(file_path.exists());
+        frecency.record(Path::new("/b"));
+        frecency.save().unwrap();
+        let loaded = MmmFrecency::load(Some(&data_home));
+        assert_eq!(loaded.entries.len(), 2);
+        assert_eq!(loaded.entries[0].rank, SAVE_EVERY as f64);
+    }
+
+    #[test]
+    fn saving_keeps_visits_saved_by_another_instance() {
+        let data_home = make_data_home();
+        let mut first = MmmFrecency::load(Some(&data_home));
+        let mut second = MmmFrecency::load(Some(&data_home));
+        first.record(Path::new("/a"));
+        first.record(Path::new("/both"));
+        first.save().unwrap();
+        second.record(Path::new("/b"));
+        second.record(Path::new("/both"));
+        second.save().unwrap();
+        let mut loaded = MmmFrecency::load(Some(&data_home)).entries;
+        loaded.sort_by(|a, b| a.path.cmp(&b.path));
+        let ranks: Vec<(&Path, f64)> = loaded
+            .iter()
+            .map(|entry| (entry.path.as_path(), entry.rank))
+            .collect();
+        assert_eq!(
+            ranks,
+            vec![
+                (Path::new("/a"), 1.0),
+                (Path::new("/b"), 1.0),
+                (Path::new("/both"), 2.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn paths_that_cannot_be_saved_are_not_recorded() {
+        let mut frecency = MmmFrecency::default();
+        frecency.record(Path::new("/tab\there"));
+        frecency.record(Path::new("/new\nline"));
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+            frecency.record(Path::new(OsStr::from_bytes(b"/caf\xe9")));
+        }
+        assert!(frecency.entries.is_empty());
+        assert!(frecency.unsaved.is_empty());
+    }
+}
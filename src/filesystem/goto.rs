@@ -0,0 +1,134 @@
+use crate::error_type::MmmResult;
+use std::path::{Component, Path, PathBuf};
+
+/// Splits typed path input into the directory part, up to and including the last `/`, and the
+/// partial name being typed after it
+pub fn split_path_input(input: &str) -> (&str, &str) {
+    match input.rfind('/') {
+        Some(idx) => input.split_at(idx + 1),
+        None => ("", input),
+    }
+}
+
+/// Expands a leading `~` and any `$VAR` or `${VAR}` in typed path input, then resolves it against
+/// `base` and removes `.` and `..` components the way `cd` does. A `$` that isn't followed by a
+/// set variable is kept as it is, as it may be part of a name.
+pub fn expand_path_input(input: &str, base: &Path) -> MmmResult<PathBuf> {
+    expand_path_input_with(input, base, |name| std::env::var(name).ok())
+}
+
+/// Expands path input as `expand_path_input` does, looking variables up with `lookup`
+fn expand_path_input_with(
+    input: &str,
+    base: &Path,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> MmmResult<PathBuf> {
+    let mut expanded = String::new();
+    let mut rest = input;
+    if let Some(after_tilde) = rest.strip_prefix('~') {
+        if after_tilde.is_empty() || after_tilde.starts_with('/') {
+            expanded += &lookup("HOME").ok_or("$HOME is not set")?;
+            rest = after_tilde;
+        }
+    }
+    while let Some(idx) = rest.find('$') {
+        expanded += &rest[..idx];
+        let after = &rest[idx + 1..];
+        let (name, remaining) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                after.split_at(end)
+            }
+        };
+        match lookup(name) {
+            Some(value) if !name.is_empty() => {
+                expanded += &value;
+                rest = remaining;
+            }
+            _ => {
+                expanded.push('$');
+                rest = after;
+            }
+        }
+    }
+    expanded += rest;
+    Ok(normalise_path(&base.join(expanded)))
+}
+
+/// Removes `.` and `..` components without touching the filesystem, so a `..` after a symlink
+/// goes back to where the link was rather than to the parent of its target
+fn normalise_path(path: &Path) -> PathBuf {
+    let mut normalised = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalised.pop();
+            }
+            component => normalised.push(component),
+        }
+    }
+    normalised
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expands against a fixed set of variables, so nothing depends on the environment the tests
+    /// run in
+    fn expand(input: &str) -> PathBuf {
+        let lookup = |name: &str| match name {
+            "HOME" => Some("/home/me".to_string()),
+            "PROJECT" => Some("/work/mmm".to_string()),
+            _ => None,
+        };
+        expand_path_input_with(input, Path::new("/base/dir"), lookup).unwrap()
+    }
+
+    #[test]
+    fn input_is_split_at_the_last_slash() {
+        assert_eq!(split_path_input("~/src/mm"), ("~/src/", "mm"));
+        assert_eq!(split_path_input("/"), ("/", ""));
+        assert_eq!(split_path_input("name"), ("", "name"));
+    }
+
+    #[test]
+    fn paths_are_resolved_like_cd() {
+        assert_eq!(expand("sub/./x"), PathBuf::from("/base/dir/sub/x"));
+        assert_eq!(expand("../other"), PathBuf::from("/base/other"));
+        assert_eq!(expand("/etc/../tmp/"), PathBuf::from("/tmp"));
+    }
+
+    #[test]
+    fn set_variables_are_expanded() {
+        assert_eq!(expand("~/x"), PathBuf::from("/home/me/x"));
+        assert_eq!(expand("~"), PathBuf::from("/home/me"));
+        assert_eq!(expand("$HOME/x"), PathBuf::from("/home/me/x"));
+        assert_eq!(expand("${PROJECT}x"), PathBuf::from("/work/mmmx"));
+        assert_eq!(expand("$PROJECT/../y"), PathBuf::from("/work/y"));
+    }
+
+    #[test]
+    fn tilde_needs_home() {
+        assert!(expand_path_input_with("~/x", Path::new("/"), |_| None).is_err());
+    }
+
+    #[test]
+    fn other_dollars_are_kept() {
+        assert_eq!(expand("/a$/b"), PathBuf::from("/a$/b"));
+        assert_eq!(expand("/price$5"), PathBuf::from("/price$5"));
+        assert_eq!(
+            expand("/$MMM_SURELY_NOT_SET/x"),
+            PathBuf::from("/$MMM_SURELY_NOT_SET/x")
+        );
+        assert_eq!(expand("/a/${open"), PathBuf::from("/a/${open"));
+        assert_eq!(expand("/a/${}"), PathBuf::from("/a/${}"));
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+/// A directory as it was when it was left, enough to put the view back as it was
+#[derive(Clone, Debug, PartialEq)]
+pub struct MmmHistoryEntry {
+    pub path: PathBuf,
+    pub filter: String,
+    pub selected: Option<PathBuf>,
+}
+
+/// Browser style back and forward stacks of the directories visited this session
+#[derive(Debug, Default)]
+pub struct MmmHistory {
+    back_stack: Vec<MmmHistoryEntry>,
+    forward_stack: Vec<MmmHistoryEntry>,
+}
+
+impl MmmHistory {
+    /// Records leaving a directory by ordinary navigation, which invalidates the forward stack
+    pub fn push(&mut self, entry: MmmHistoryEntry) {
+        self.back_stack.push(entry);
+        self.forward_stack.clear();
+    }
+
+    /// Swaps the current view for the previous one, returning the view to restore
+    pub fn back(&mut self, current: MmmHistoryEntry) -> Option<MmmHistoryEntry> {
+        let entry = self.back_stack.pop()?;
+        self.forward_stack.push(current);
+        Some(entry)
+    }
+
+    /// Swaps the current view for the next one, returning the view to restore
+    pub fn forward(&mut self, current: MmmHistoryEntry) -> Option<MmmHistoryEntry> {
+        let entry = self.forward_stack.pop()?;
+        self.back_stack.push(current);
+        Some(entry)
+    }
+
+    /// Undoes a `back` or `forward` whose view could not be restored
+    pub fn revert(&mut self, went_back: bool, entry: MmmHistoryEntry) {
+        if went_back {
+            self.forward_stack.pop();
+            self.back_stack.push(entry);
+        } else {
+            self.back_stack.pop();
+            self.forward_stack.push(entry);
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use super::ops::{
+    copy_path_with_progress, crosses_devices, get_modified, get_unique_path, remove_path,
+    require_not_inside, trash_path, MmmFileOp,
+};
+use crate::error_type::{MmmError, MmmResult};
+use futures::FutureExt;
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::task::JoinHandle;
+
+/// What to do when a pasted entry already exists in the destination
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MmmConflictPolicy {
+    Skip,
+    Overwrite,
+    #[default]
+    Rename,
+}
+
+impl MmmConflictPolicy {
+    pub fn next(self) -> Self {
+        match self {
+            MmmConflictPolicy::Skip => MmmConflictPolicy::Overwrite,
+            MmmConflictPolicy::Overwrite => MmmConflictPolicy::Rename,
+            MmmConflictPolicy::Rename => MmmConflictPolicy::Skip,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        match self {
+            MmmConflictPolicy::Skip => "skip",
+            MmmConflictPolicy::Overwrite => "overwrite",
+            MmmConflictPolicy::Rename => "rename",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MmmJobProgress {
+    bytes_done: AtomicU64,
+    bytes_total: AtomicU64,
+    files_done: AtomicU64,
+    files_total: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+impl MmmJobProgress {
+    fn add(&self, bytes: u64, files: u64) -> MmmResult<()> {
+        self.bytes_done.fetch_add(bytes, Ordering::Relaxed);
+        self.files_done.fetch_add(files, Ordering::Relaxed);
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err("cancelled".into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct MmmJob {
+    paths: Vec<PathBuf>,
+    dest_dir: PathBuf,
+    cut: bool,
+    policy: MmmConflictPolicy,
+    trash_dir: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+struct MmmRunningJob {
+    cut: bool,
+    progress: Arc<MmmJobProgress>,
+    handle: JoinHandle<MmmJobOutcome>,
+}
+
+/// The operations a finished job performed, and the error that stopped it early if any
+#[derive(Debug)]
+pub struct MmmJobOutcome {
+    pub ops: Vec<MmmFileOp>,
+    pub error: Option<MmmError>,
+    pub cancelled: bool,
+}
+
+/// A snapshot of the running job for display
+pub struct MmmJobStatus {
+    pub label: String,
+    pub fraction: f64,
+}
+
+/// Copy and move jobs, run one at a time on tokio's blocking pool so the event loop stays live
+#[derive(Debug, Default)]
+pub struct MmmJobQueue {
+    queue: VecDeque<MmmJob>,
+    running: Option<MmmRunningJob>,
+    pub policy: MmmConflictPolicy,
+    /// Where entries replaced by the overwrite policy are trashed to
+    pub trash_dir: Option<PathBuf>,
+}
+
+impl MmmJobQueue {
+    pub fn push(&mut self, paths: Vec<PathBuf>, dest_dir: PathBuf, cut: bool) {
+        self.queue.push_back(MmmJob {
+            paths,
+            dest_dir,
+            cut,
+            policy: self.policy,
+            trash_dir: self.trash_dir.clone(),
+        });
+        self.start_next();
+    }
+
+    fn start_next(&mut self) {
+        if self.running.is_some() {
+            return;
+        }
+        if let Some(job) = self.queue.pop_front() {
+            let progress = Arc::new(MmmJobProgress::default());
+            let job_progress = progress.clone();
+            let cut = job.cut;
+            let handle = tokio::task::spawn_blocking(move || run_job(job, &job_progress));
+            self.running = Some(MmmRunningJob {
+                cut,
+                progress,
+                handle,
+            });
+        }
+    }
+
+    /// Collects the outcome of the running job once it finishes and starts the next one
+    pub fn poll(&mut self) -> Option<MmmJobOutcome> {
+        if !self.running.as_ref()?.handle.is_finished() {
+            return None;
+        }
+        let running = self.running.take()?;
+        let mut outcome = match running.handle.now_or_never()? {
+            Ok(outcome) => outcome,
+            Err(err) => MmmJobOutcome {
+                ops: vec![],
+                error: Some(format!("job failed, {}", err).into()),
+                cancelled: false,
+            },
+        };
+        outcome.cancelled = running.progress.cancelled.load(Ordering::Relaxed);
+        self.start_next();
+        Some(outcome)
+    }
+
+    /// Stops the running job after its current chunk and drops everything queued
+    pub fn cancel(&mut self) {
+        self.queue.clear();
+        if let Some(running) = &self.running {
+            running.progress.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.running.is_some()
+    }
+
+    pub fn get_status(&self) -> Option<MmmJobStatus> {
+        let running = self.running.as_ref()?;
+        let progress = &running.progress;
+        let bytes_done = progress.bytes_done.load(Ordering::Relaxed);
+        let bytes_total = progress.bytes_total.load(Ordering::Relaxed);
+        let files_done = progress.files_done.load(Ordering::Relaxed);
+        let files_total = progress.files_total.load(Ordering::Relaxed);
+        let mut label = format!(
+            "{} {}/{} files {}/{}",
+            if running.cut { "moving" } else { "copying" },
+            files_done,
+            files_total,
+            super::dir_entry::format_size(bytes_done),
+            super::dir_entry::format_size(bytes_total)
+        );
+        if !self.queue.is_empty() {
+            label += &format!(" +{} queued", self.queue.len());
+        }
+        let fraction = if bytes_total > 0 {
+            bytes_done as f64 / bytes_total as f64
+        } else if files_total > 0 {
+            files_done as f64 / files_total as f64
+        } else {
+            0.0
+        };
+        Some(MmmJobStatus {
+            label,
+            fraction: fraction.min(1.0),
+        })
+    }
+}
+
+/// Counts the bytes and entries under a path without following symlinks
+fn get_path_stats(path: &Path) -> (u64, u64) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return (0, 0);
+    };
+    if metadata.file_type().is_symlink() {
+        (0, 1)
+    } else if metadata.is_dir() {
+        fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| get_path_stats(&entry.path()))
+                    .fold((0, 1), |acc, stats| (acc.0 + stats.0, acc.1 + stats.1))
+            })
+            .unwrap_or((0, 1))
+    } else {
+        (metadata.len(), 1)
+    }
+}
+
+fn run_job(job: MmmJob, progress: &MmmJobProgress) -> MmmJobOutcome {
+    let stats: Vec<(u64, u64)> = job.paths.iter().map(|path| get_path_stats(path)).collect();
+    progress
+        .bytes_total
+        .store(stats.iter().map(|stats| stats.0).sum(), Ordering::Relaxed);
+    progress
+        .files_total
+        .store(stats.iter().map(|stats| stats.1).sum(), Ordering::Relaxed);
+    let mut ops = vec![];
+    for path in &job.paths {
+        if let Err(err) = run_job_entry(&job, path, progress, &mut ops) {
+            return MmmJobOutcome {
+                ops,
+                error: Some(err),
+                cancelled: false,
+            };
+        }
+    }
+    MmmJobOutcome {
+        ops,
+        error: None,
+        cancelled: false,
+    }
+}
+
+fn run_job_entry(
+    job: &MmmJob,
+    path: &Path,
+    progress: &MmmJobProgress,
+    ops: &mut Vec<MmmFileOp>,
+) -> MmmResult<()> {
+    progress.add(0, 0)?;
+    let name = path
+        .file_name()
+        .ok_or(format!("cannot paste {}", path.display()))?
+        .to_string_lossy()
+        .to_string();
+    // Checked before any conflict handling, so a rejected paste never trashes what it would replace
+    require_not_inside(path, &job.dest_dir)?;
+    let mut dest = job.dest_dir.join(&name);
+    if dest == path {
+        if job.cut {
+            let (bytes, files) = get_path_stats(path);
+            return progress.add(bytes, files);
+        }
+        dest = get_unique_path(&job.dest_dir, &name);
+    } else if fs::symlink_metadata(&dest).is_ok() {
+        match job.policy {
+            MmmConflictPolicy::Skip => {
+                let (bytes, files) = get_path_stats(path);
+                return progress.add(bytes, files);
+            }
+            MmmConflictPolicy::Rename => dest = get_unique_path(&job.dest_dir, &name),
+            // The old entry goes to the trash so the overwrite can be undone
+            MmmConflictPolicy::Overwrite => ops.push(trash_path(&dest, job.trash_dir.as_deref())?),
+        }
+    }
+    let on_progress = |bytes, files| progress.add(bytes, files);
+    if job.cut {
+        match fs::rename(path, &dest) {
+            Ok(()) => {
+                let (bytes, files) = get_path_stats(&dest);
+                progress.add(bytes, files)?;
+            }
+            Err(err) if crosses_devices(&err) => {
+                copy_or_clean_up(path, &dest, &on_progress)?;
+                remove_path(path)?;
+            }
+            Err(err) => return Err(err.into()),
+        }
+        ops.push(MmmFileOp::Move {
+            from: path.to_path_buf(),
+            to: dest,
+        });
+    } else {
+        copy_or_clean_up(path, &dest, &on_progress)?;
+        ops.push(MmmFileOp::Copy {
+            from: path.to_path_buf(),
+            modified: get_modified(&dest),
+            to: dest,
+        });
+    }
+    Ok(())
+}
+
+/// Copies a path, removing the partial copy if the copy fails or is cancelled
+fn copy_or_clean_up(
+    from: &Path,
+    to: &Path,
+    on_progress: &dyn Fn(u64, u64) -> MmmResult<()>,
+) -> MmmResult<()> {
+    let result = copy_path_with_progress(from, to, on_progress);
+    if result.is_err() {
+        let _ = remove_path(to);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{make_data_home, make_tree};
+
+    #[test]
+    fn overwriting_inside_itself_is_refused_before_trashing() {
+        let root = make_tree("jobs-inside", &["alpha/alpha"], &["alpha/alpha/one.txt"]);
+        let data_home = make_data_home();
+        let job = MmmJob {
+            paths: vec![root.join("alpha")],
+            dest_dir: root.join("alpha"),
+            cut: false,
+            policy: MmmConflictPolicy::Overwrite,
+            trash_dir: Some(data_home.join("Trash")),
+        };
+        let mut ops = Vec::new();
+        let result = run_job_entry(
+            &job,
+            &root.join("alpha"),
+            &MmmJobProgress::default(),
+            &mut ops,
+        );
+        assert!(result.is_err());
+        assert!(ops.is_empty());
+        assert!(root.join("alpha/alpha/one.txt").exists());
+    }
+}
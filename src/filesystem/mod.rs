@@ -1,11 +1,35 @@
+pub(crate) mod bookmarks;
+pub(crate) mod bulk_rename;
+pub(crate) mod data_file;
 pub(crate) mod dir_entry;
 pub(crate) mod filter;
+pub(crate) mod frecency;
+pub(crate) mod goto;
+pub(crate) mod history;
+pub(crate) mod jobs;
+pub(crate) mod ops;
+pub(crate) mod picker;
+pub(crate) mod shell;
+pub(crate) mod xdg;
 
 use crate::error_type::MmmResult;
 use crate::filesystem::filter::filter_hidden;
+use bookmarks::MmmBookmarks;
+use bulk_rename::{order_renames, plan_renames};
 use dir_entry::get_dir_list;
 use filter::{filter_and_score, filter_hidden_with_exception, FilterMatchEnum};
+use frecency::MmmFrecency;
+use goto::{expand_path_input, split_path_input};
+use history::{MmmHistory, MmmHistoryEntry};
+use jobs::{MmmConflictPolicy, MmmJobQueue, MmmJobStatus};
+use ops::{make_dir, rename_path, trash_path, MmmClipboard, MmmFileOp, MmmJournal};
+use picker::{MmmPickKind, MmmPicker};
+use shell::MmmCommandContext;
 use std::{
+    cmp::min,
+    collections::{BTreeSet, HashMap},
+    ffi::OsString,
+    fs,
     path::{Path, PathBuf},
     rc::Rc,
 };
@@ -25,6 +49,7 @@ pub enum MmmDirEntry {
         name: String,
         path: PathBuf,
         linked_path: Option<PathBuf>,
+        target: MmmLinkTarget,
     },
     Other {
         name: String,
@@ -32,6 +57,14 @@ pub enum MmmDirEntry {
     },
 }
 
+/// What a symlink resolves to once every link in the chain is followed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MmmLinkTarget {
+    Directory,
+    File,
+    Broken,
+}
+
 impl MmmDirEntry {
     #[allow(unused)]
     pub fn get_path(&self) -> &Path {
@@ -42,6 +75,28 @@ impl MmmDirEntry {
             MmmDirEntry::Other { path, .. } => path,
         }
     }
+    /// Whether the entry can be navigated into, a directory or a link to one
+    pub fn is_dir_like(&self) -> bool {
+        matches!(
+            self,
+            MmmDirEntry::Directory { .. }
+                | MmmDirEntry::Link {
+                    target: MmmLinkTarget::Directory,
+                    ..
+                }
+        )
+    }
+
+    pub fn is_broken_link(&self) -> bool {
+        matches!(
+            self,
+            MmmDirEntry::Link {
+                target: MmmLinkTarget::Broken,
+                ..
+            }
+        )
+    }
+
     pub fn get_name(&self) -> &str {
         match self {
             MmmDirEntry::File { name, .. } => name.as_ref(),
@@ -52,6 +107,34 @@ impl MmmDirEntry {
     }
 }
 
+/// What the current pane is listing and how keys are interpreted
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MmmMode {
+    #[default]
+    Browse,
+    AwaitBookmarkKey,
+    Bookmarks,
+    /// Typing a new name for the bookmark under the key, the bookmark list stays as it was
+    RenameBookmark(char),
+    Jump,
+    GoTo,
+    Commands,
+}
+
+impl MmmMode {
+    pub fn get_name(&self) -> Option<&str> {
+        match self {
+            MmmMode::Browse => None,
+            MmmMode::AwaitBookmarkKey => Some("bookmark key"),
+            MmmMode::Bookmarks => Some("bookmarks"),
+            MmmMode::RenameBookmark(_) => Some("rename bookmark"),
+            MmmMode::Jump => Some("jump"),
+            MmmMode::GoTo => Some("go to"),
+            MmmMode::Commands => Some("commands"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct MmmScoredDirEntry {
     pub entry: Rc<MmmDirEntry>,
@@ -62,34 +145,131 @@ pub struct MmmScoredDirEntry {
 #[derive(Debug)]
 pub struct MmmFilesys {
     filter: String,
+    /// Text typed into a prompt, kept apart from the filter so typing it doesn't narrow the list
+    prompt: String,
     current_path: PathBuf,
     current_dir_list: Vec<Rc<MmmDirEntry>>,
     pub filtered_current_dir_list: Vec<MmmScoredDirEntry>,
     selected_entry: usize,
     pub filtered_parent_dir_list: Option<Vec<Rc<MmmDirEntry>>>,
     pub parent_current_entry: usize,
+    /// The first entry shown in the current pane
+    pub current_top_row: usize,
+    /// The first entry shown in the parent pane once it has been scrolled away from the
+    /// selected entry
+    pub parent_top_row: Option<usize>,
     pub show_hidden_files: bool,
+    journal: MmmJournal,
+    /// The freedesktop.org trash under the data directory, if there is one
+    trash_dir: Option<PathBuf>,
+    clipboard: Option<MmmClipboard>,
+    marked: BTreeSet<PathBuf>,
+    jobs: MmmJobQueue,
+    mode: MmmMode,
+    bookmarks: MmmBookmarks,
+    frecency: MmmFrecency,
+    /// The history listed by jump mode, read when it was opened
+    jump_entries: Vec<Rc<MmmDirEntry>>,
+    /// The directory input of the go to prompt and the entries read for it
+    completions: (String, Vec<Rc<MmmDirEntry>>),
+    history: MmmHistory,
+    pub remember_selection: bool,
+    pub physical_paths: bool,
+    pub auto_enter: bool,
+    /// User commands from the config as `(name, command)`, in the order they were defined
+    pub commands: Vec<(String, String)>,
+    command_context: MmmCommandContext,
+    /// Set when running as a chooser, Enter then picks entries instead of doing nothing
+    pub picker: Option<MmmPicker>,
+    picked: Option<Vec<PathBuf>>,
+    /// The filter and selection each directory was left with this session
+    remembered: HashMap<PathBuf, MmmHistoryEntry>,
 }
 
 impl MmmFilesys {
-    pub fn from_path(current_path: PathBuf) -> MmmResult<MmmFilesys> {
+    /// Opens `current_path`, keeping bookmarks, history and the trash under `data_home`. Without
+    /// a data directory nothing is saved and nothing can be trashed.
+    pub fn from_path(current_path: PathBuf, data_home: Option<&Path>) -> MmmResult<MmmFilesys> {
+        let trash_dir = data_home.map(|data_home| data_home.join("Trash"));
         let mut filesys = MmmFilesys {
             filter: String::new(),
+            prompt: String::new(),
             current_path: PathBuf::new(),
             current_dir_list: Vec::new(),
             selected_entry: 0,
             filtered_current_dir_list: Vec::new(),
             filtered_parent_dir_list: None,
             parent_current_entry: 0,
+            current_top_row: 0,
+            parent_top_row: None,
             show_hidden_files: false,
+            journal: MmmJournal::default(),
+            trash_dir,
+            clipboard: None,
+            marked: BTreeSet::new(),
+            jobs: MmmJobQueue::default(),
+            mode: MmmMode::Browse,
+            bookmarks: MmmBookmarks::load(data_home),
+            frecency: MmmFrecency::load(data_home),
+            jump_entries: Vec::new(),
+            completions: Default::default(),
+            history: MmmHistory::default(),
+            remember_selection: true,
+            physical_paths: false,
+            auto_enter: false,
+            commands: Vec::new(),
+            command_context: MmmCommandContext::default(),
+            picker: None,
+            picked: None,
+            remembered: HashMap::new(),
         };
+        filesys.jobs.trash_dir = filesys.trash_dir.clone();
         filesys.change_directory(current_path)?;
         Ok(filesys)
     }
 
+    /// Moves to a new directory, recording the one being left in the back/forward history
     pub fn change_directory(&mut self, path: PathBuf) -> MmmResult<()> {
+        let leaving = self.get_history_entry();
+        let moved = path != self.current_path;
+        self.load_directory(path)?;
+        if moved && !leaving.path.as_os_str().is_empty() {
+            if self.remember_selection {
+                self.remembered
+                    .insert(leaving.path.clone(), leaving.clone());
+            }
+            // Coming up out of a directory puts the cursor on the child it was left through
+            let child = leaving
+                .path
+                .strip_prefix(&self.current_path)
+                .ok()
+                .and_then(|rel| rel.components().next())
+                .map(|component| self.current_path.join(component));
+            if let Some(child) = child {
+                self.select_path(&child);
+            } else if let (true, Some(remembered)) = (
+                self.remember_selection,
+                self.remembered.get(&self.current_path).cloned(),
+            ) {
+                self.apply_history_entry(&remembered);
+            }
+            self.history.push(leaving);
+        }
+        Ok(())
+    }
+
+    fn load_directory(&mut self, path: PathBuf) -> MmmResult<()> {
+        // Read the new listings before touching any state so a failure leaves the old one intact
+        let current_dir_list = get_dir_list(&path)
+            .map_err(|err| format!("cannot read {}, {}", path.display(), err))?;
+        // A parent that can't be read only costs the parent pane
+        let parent_dir_list = path.parent().and_then(|parent| get_dir_list(parent).ok());
+        if path != self.current_path {
+            self.frecency.record(&path);
+        }
         self.current_path = path;
-        self.current_dir_list = get_dir_list(&self.current_path)?;
+        self.current_dir_list = current_dir_list;
+        self.mode = MmmMode::Browse;
         self.selected_entry = 0;
         self.filtered_current_dir_list = self
             .current_dir_list
@@ -97,52 +277,472 @@ impl MmmFilesys {
             .filter_map(|entry| filter_hidden(entry.clone(), self.show_hidden_files))
             .filter_map(|entry| filter_and_score(entry.clone(), ""))
             .collect();
-        self.filtered_parent_dir_list = self
-            .current_path
-            .parent()
-            .map(get_dir_list)
-            .transpose()?
-            .map(|list| {
-                list.into_iter()
-                    .filter_map(|entry| {
-                        filter_hidden_with_exception(
-                            entry.clone(),
-                            self.show_hidden_files,
-                            self.current_path
-                                .file_name()
-                                .and_then(|name| name.to_str())?,
-                        )
-                    })
-                    .collect()
-            });
-        self.parent_current_entry = if let Some(pdl) = &self.filtered_parent_dir_list {
-            pdl.iter()
-                .position(|entry| entry.get_path() == self.current_path)
-                .expect("cannot find parent dir")
-        } else {
-            0
-        };
+        self.filtered_parent_dir_list = parent_dir_list.map(|list| {
+            list.into_iter()
+                .filter_map(|entry| {
+                    filter_hidden_with_exception(
+                        entry.clone(),
+                        self.show_hidden_files,
+                        &self.current_path,
+                    )
+                })
+                .collect()
+        });
+        // The directory can be missing from a listing read a moment later, if it was removed
+        self.parent_current_entry = self
+            .filtered_parent_dir_list
+            .as_ref()
+            .and_then(|pdl| {
+                pdl.iter()
+                    .position(|entry| entry.get_path() == self.current_path)
+            })
+            .unwrap_or(0);
+        self.parent_top_row = None;
         self.filter = String::new();
         Ok(())
     }
 
+    /// Re-reads the current directory after it has been modified, keeping the filter and mode
+    pub fn refresh(&mut self) -> MmmResult<()> {
+        let filter = self.filter.clone();
+        let mode = self.mode;
+        let selected = self.get_selected_entry();
+        self.load_directory(self.current_path.clone())?;
+        self.filter = filter;
+        self.mode = mode;
+        self.populate_filtered_list();
+        if let Some(selected) = selected {
+            self.select_path(selected.get_path());
+        }
+        Ok(())
+    }
+
+    fn get_history_entry(&self) -> MmmHistoryEntry {
+        // Other modes list something other than the directory, so only browsing is worth restoring
+        if self.mode != MmmMode::Browse {
+            return MmmHistoryEntry {
+                path: self.current_path.clone(),
+                filter: String::new(),
+                selected: None,
+            };
+        }
+        MmmHistoryEntry {
+            path: self.current_path.clone(),
+            filter: self.filter.clone(),
+            selected: self
+                .get_selected_entry()
+                .map(|entry| entry.get_path().to_path_buf()),
+        }
+    }
+
+    fn restore_history_entry(&mut self, entry: &MmmHistoryEntry) -> MmmResult<()> {
+        self.load_directory(entry.path.clone())?;
+        self.apply_history_entry(entry);
+        Ok(())
+    }
+
+    /// Puts back the filter and selection of an entry for the directory already loaded
+    fn apply_history_entry(&mut self, entry: &MmmHistoryEntry) {
+        self.filter = entry.filter.clone();
+        self.populate_filtered_list();
+        if let Some(selected) = &entry.selected {
+            self.select_path(selected);
+        }
+    }
+
+    pub fn history_back(&mut self) -> MmmResult<()> {
+        let current = self.get_history_entry();
+        let entry = self.history.back(current).ok_or("no earlier directory")?;
+        if let Err(err) = self.restore_history_entry(&entry) {
+            self.history.revert(true, entry);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub fn history_forward(&mut self) -> MmmResult<()> {
+        let current = self.get_history_entry();
+        let entry = self.history.forward(current).ok_or("no later directory")?;
+        if let Err(err) = self.restore_history_entry(&entry) {
+            self.history.revert(false, entry);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Moves the selection onto the entry with the given path if it is listed
+    pub fn select_path(&mut self, path: &Path) {
+        if let Some(idx) = self
+            .filtered_current_dir_list
+            .iter()
+            .position(|scored| scored.entry.get_path() == path)
+        {
+            self.selected_entry = idx;
+        }
+    }
+
+    pub fn get_selected_index(&self) -> usize {
+        self.selected_entry
+    }
+
     pub fn get_current_path(&self) -> &Path {
         &self.current_path
     }
 
+    pub fn get_entry_count(&self) -> usize {
+        self.get_listing().len()
+    }
+
+    pub fn get_mode(&self) -> MmmMode {
+        self.mode
+    }
+
+    /// The unfiltered entries for the current pane
+    fn get_listing(&self) -> Vec<Rc<MmmDirEntry>> {
+        match self.mode {
+            MmmMode::Bookmarks | MmmMode::RenameBookmark(_) => self.bookmarks.get_entries(),
+            MmmMode::Jump => self.jump_entries.clone(),
+            MmmMode::GoTo => self.completions.1.clone(),
+            MmmMode::Commands => self.get_command_entries(),
+            MmmMode::Browse | MmmMode::AwaitBookmarkKey => self.current_dir_list.clone(),
+        }
+    }
+
+    /// The user commands as `name command` entries, with an empty path as they aren't files
+    fn get_command_entries(&self) -> Vec<Rc<MmmDirEntry>> {
+        self.commands
+            .iter()
+            .map(|(name, command)| {
+                Rc::new(MmmDirEntry::Other {
+                    name: format!("{} {}", name, command),
+                    path: PathBuf::new(),
+                })
+            })
+            .collect()
+    }
+
+    /// Reads the entries of the directory typed so far, which the name after it completes
+    /// against, unless they were already read for the same directory input
+    fn update_completions(&mut self) {
+        let (dir_input, _) = split_path_input(&self.filter);
+        if self.completions.0 == dir_input {
+            return;
+        }
+        let entries = if dir_input.is_empty() {
+            Vec::new()
+        } else {
+            expand_path_input(dir_input, &self.current_path)
+                .and_then(|dir| get_dir_list(&dir))
+                .unwrap_or_default()
+        };
+        self.completions = (dir_input.to_string(), entries);
+    }
+
+    /// The part of the filter entries are matched against, the partial name when going to a path
+    fn get_match_filter(&self) -> &str {
+        match self.mode {
+            MmmMode::GoTo => split_path_input(&self.filter).1,
+            _ => &self.filter,
+        }
+    }
+
+    /// Starts typing a path to go to, with the `/` or `~` that begins it
+    pub fn open_go_to(&mut self, c: char) {
+        self.completions = Default::default();
+        self.mode = MmmMode::GoTo;
+        self.filter = c.to_string();
+        self.populate_filtered_list();
+    }
+
+    /// Replaces the partial name being typed with the selected completion
+    pub fn complete_path(&mut self) {
+        if self.mode != MmmMode::GoTo {
+            return;
+        }
+        let (dir_input, _) = split_path_input(&self.filter);
+        let mut completed = match self.get_selected_entry() {
+            Some(entry) if !dir_input.is_empty() => dir_input.to_string() + entry.get_name(),
+            Some(_) | None => self.filter.clone(),
+        };
+        let is_dir = expand_path_input(&completed, &self.current_path)
+            .map(|path| path.is_dir())
+            .unwrap_or(false);
+        if is_dir && !completed.ends_with('/') {
+            completed.push('/');
+        }
+        self.filter = completed;
+        self.populate_filtered_list();
+    }
+
+    /// Goes to the typed path, a file is selected in the directory containing it
+    pub fn go_to_path(&mut self) -> MmmResult<()> {
+        let mut path = expand_path_input(&self.filter, &self.current_path)?;
+        if self.physical_paths {
+            path = fs::canonicalize(&path)
+                .map_err(|err| format!("cannot go to {}, {}", path.display(), err))?;
+        }
+        // Changing directory leaves the mode once it succeeds, a failure leaves the input to fix
+        if path.is_dir() {
+            self.change_directory(path)
+        } else if path.exists() {
+            let parent = path.parent().ok_or("cannot go to parent")?.to_path_buf();
+            self.change_directory(parent)?;
+            self.select_path(&path);
+            Ok(())
+        } else {
+            Err(format!("cannot go to {}, no such file or directory", path.display()).into())
+        }
+    }
+
+    /// Returns to browsing the current directory from any other mode
+    pub fn close_mode(&mut self) {
+        self.mode = MmmMode::Browse;
+        self.prompt.clear();
+        self.clear_filter();
+    }
+
+    pub fn await_bookmark_key(&mut self) {
+        self.mode = MmmMode::AwaitBookmarkKey;
+    }
+
+    pub fn set_bookmark(&mut self, key: char) -> MmmResult<()> {
+        self.mode = MmmMode::Browse;
+        self.bookmarks.set(key, &self.current_path)
+    }
+
+    pub fn jump_to_bookmark(&mut self, key: char) -> MmmResult<()> {
+        let path = self
+            .bookmarks
+            .get(key)
+            .ok_or(format!("no bookmark under '{}'", key))?
+            .path
+            .clone();
+        self.change_directory(path)
+    }
+
+    /// Lists every directory visited before, best ranked first, for fuzzy jumping
+    pub fn open_jump(&mut self) {
+        self.jump_entries = self.frecency.get_entries();
+        self.mode = MmmMode::Jump;
+        self.clear_filter();
+    }
+
+    /// Counts leaving mmm in the current directory as a visit, as a cd into it, and saves the
+    /// history
+    pub fn record_exit(&mut self) {
+        self.frecency.record(&self.current_path);
+        let _ = self.frecency.save();
+    }
+
+    /// Lists the user commands, remembering what they will act on
+    pub fn open_commands(&mut self) -> MmmResult<()> {
+        if self.commands.is_empty() {
+            return Err("no commands are set up in the config".into());
+        }
+        self.command_context = MmmCommandContext {
+            selected: self
+                .get_selected_entry()
+                .map(|dir_entry| dir_entry.get_path().to_path_buf()),
+            targets: self.get_targets(),
+            dir: self.current_path.clone(),
+            filter: self.filter.clone(),
+        };
+        self.mode = MmmMode::Commands;
+        self.clear_filter();
+        Ok(())
+    }
+
+    /// The name of the selected user command and its command line with the placeholders filled in
+    pub fn get_selected_command(&self) -> Option<(String, OsString)> {
+        if self.mode != MmmMode::Commands {
+            return None;
+        }
+        let selected = self.get_selected_entry()?;
+        let name = selected.get_name().split(' ').next()?;
+        self.commands
+            .iter()
+            .find(|(command_name, _)| command_name == name)
+            .map(|(name, command)| (name.clone(), self.command_context.expand(command)))
+    }
+
+    pub fn open_bookmarks(&mut self) {
+        self.mode = MmmMode::Bookmarks;
+        self.clear_filter();
+    }
+
+    /// The key of the selected bookmark, from the `key name` entry name
+    fn get_selected_bookmark_key(&self) -> Option<char> {
+        if self.mode != MmmMode::Bookmarks {
+            return None;
+        }
+        self.get_selected_entry()?.get_name().chars().next()
+    }
+
+    pub fn delete_selected_bookmark(&mut self) -> MmmResult<()> {
+        if let Some(key) = self.get_selected_bookmark_key() {
+            self.bookmarks.remove(key)?;
+            self.populate_filtered_list();
+        }
+        Ok(())
+    }
+
+    /// Starts a prompt for the selected bookmark's new name, filled in with its current name.
+    /// The key is kept so the bookmark renamed is the one selected now.
+    pub fn start_bookmark_rename(&mut self) {
+        if let Some(key) = self.get_selected_bookmark_key() {
+            self.prompt = self
+                .bookmarks
+                .get(key)
+                .map(|bookmark| bookmark.name.clone())
+                .unwrap_or_default();
+            self.mode = MmmMode::RenameBookmark(key);
+        }
+    }
+
+    /// Renames the bookmark to the prompt and goes back to the bookmark list
+    pub fn finish_bookmark_rename(&mut self) -> MmmResult<()> {
+        let MmmMode::RenameBookmark(key) = self.mode else {
+            return Ok(());
+        };
+        self.bookmarks.rename(key, &self.prompt)?;
+        self.cancel_prompt();
+        self.clear_filter();
+        Ok(())
+    }
+
+    /// Leaves the prompt without doing anything, back to the mode it was opened from
+    pub fn cancel_prompt(&mut self) {
+        if let MmmMode::RenameBookmark(_) = self.mode {
+            self.mode = MmmMode::Bookmarks;
+        }
+        self.prompt.clear();
+    }
+
+    /// The prompt text when a prompt is open
+    pub fn get_prompt(&self) -> Option<&str> {
+        match self.mode {
+            MmmMode::RenameBookmark(_) => Some(&self.prompt),
+            _ => None,
+        }
+    }
+
+    pub fn prompt_add_char(&mut self, c: char) {
+        self.prompt.push(c);
+    }
+
+    pub fn prompt_pop_char(&mut self) {
+        self.prompt.pop();
+    }
+
     pub fn increment_current_selected(&mut self) {
-        // Disabled until implemented properly
-        // self.selected_entry = max(self.selected_entry + 1, max(self.current_dir_list.len(), 0));
+        self.selected_entry = min(
+            self.selected_entry + 1,
+            self.filtered_current_dir_list.len().saturating_sub(1),
+        );
     }
 
     pub fn decrement_current_selected(&mut self) {
-        // Disabled until implemented properly
-        // self.selected_entry = min(self.selected_entry - 1, 0);
+        self.selected_entry = self.selected_entry.saturating_sub(1);
+    }
+
+    /// Scrolls the current pane just far enough to show the selected entry in `rows` rows
+    pub fn keep_selection_in_view(&mut self, rows: usize) {
+        if self.selected_entry < self.current_top_row {
+            self.current_top_row = self.selected_entry;
+        } else if self.selected_entry >= self.current_top_row + rows {
+            self.current_top_row = self.selected_entry + 1 - rows;
+        }
+        self.current_top_row = min(
+            self.current_top_row,
+            self.filtered_current_dir_list.len().saturating_sub(rows),
+        );
+    }
+
+    /// Scrolls the current pane by `delta` rows, dragging the selection along to stay in view
+    pub fn scroll_current(&mut self, delta: isize, rows: usize) {
+        let max_top_row = self.filtered_current_dir_list.len().saturating_sub(rows);
+        self.current_top_row = min(
+            self.current_top_row.saturating_add_signed(delta),
+            max_top_row,
+        );
+        self.selected_entry = self.selected_entry.clamp(
+            self.current_top_row,
+            (self.current_top_row + rows)
+                .saturating_sub(1)
+                .max(self.current_top_row),
+        );
+        self.selected_entry = min(
+            self.selected_entry,
+            self.filtered_current_dir_list.len().saturating_sub(1),
+        );
+    }
+
+    /// Scrolls the parent pane by `delta` rows from `top_row`, the first entry it shows
+    pub fn scroll_parent(&mut self, top_row: usize, delta: isize, rows: usize) {
+        let len = self.filtered_parent_dir_list.as_ref().map_or(0, Vec::len);
+        self.parent_top_row = Some(min(
+            top_row.saturating_add_signed(delta),
+            len.saturating_sub(rows),
+        ));
+    }
+
+    pub fn select_index(&mut self, idx: usize) {
+        if idx < self.filtered_current_dir_list.len() {
+            self.selected_entry = idx;
+        }
+    }
+
+    /// Moves across to the next directory in the parent pane, or the previous one if `forward`
+    /// isn't set, without going up first
+    pub fn change_to_sibling(&mut self, forward: bool) -> MmmResult<()> {
+        let siblings = self
+            .filtered_parent_dir_list
+            .as_ref()
+            .ok_or("no sibling directories")?;
+        let sibling = if forward {
+            siblings
+                .iter()
+                .skip(self.parent_current_entry + 1)
+                .find(|entry| entry.is_dir_like())
+        } else {
+            siblings
+                .iter()
+                .take(self.parent_current_entry)
+                .rev()
+                .find(|entry| entry.is_dir_like())
+        };
+        let path = sibling
+            .ok_or(if forward {
+                "no next sibling directory"
+            } else {
+                "no previous sibling directory"
+            })?
+            .get_path()
+            .to_path_buf();
+        self.change_directory(path)
+    }
+
+    /// Goes up to the parent directory with the entry at `idx` of the parent pane selected
+    pub fn nav_to_parent_entry(&mut self, idx: usize) -> MmmResult<()> {
+        let entry = self
+            .filtered_parent_dir_list
+            .as_ref()
+            .and_then(|list| list.get(idx))
+            .cloned();
+        if let (Some(entry), Some(parent)) = (entry, self.current_path.parent()) {
+            self.change_directory(parent.to_path_buf())?;
+            self.select_path(entry.get_path());
+        }
+        Ok(())
     }
 
     pub fn toggle_show_hidden_files(&mut self) -> MmmResult<()> {
         self.show_hidden_files = !self.show_hidden_files;
-        self.change_directory(self.current_path.clone())
+        let result = self.change_directory(self.current_path.clone());
+        if result.is_err() {
+            self.show_hidden_files = !self.show_hidden_files;
+        }
+        result
     }
 
     pub fn clear_filter(&mut self) {
@@ -150,9 +750,29 @@ impl MmmFilesys {
         self.populate_filtered_list();
     }
 
-    pub fn filter_add_char(&mut self, c: char) {
+    /// Adds to the filter, entering the directory it leaves if it is the only match and
+    /// `auto_enter` is set
+    pub fn filter_add_char(&mut self, c: char) -> MmmResult<()> {
         self.filter.push(c);
         self.populate_filtered_list();
+        if self.auto_enter
+            && self.mode == MmmMode::Browse
+            && self.filtered_current_dir_list.len() == 1
+            && self.filtered_current_dir_list[0].entry.is_dir_like()
+        {
+            self.try_nav_into()?;
+        }
+        Ok(())
+    }
+
+    /// Removes the last character of the filter, deleting all of it leaves the mode
+    pub fn filter_pop_char(&mut self) {
+        self.filter.pop();
+        if self.filter_is_empty() {
+            self.close_mode();
+        } else {
+            self.populate_filtered_list();
+        }
     }
 
     pub fn get_selected_entry(&self) -> Option<Rc<MmmDirEntry>> {
@@ -174,9 +794,14 @@ impl MmmFilesys {
     }
 
     fn populate_filtered_list(&mut self) {
-        if self.filter_is_empty() {
-            self.filtered_current_dir_list = self
-                .current_dir_list
+        if self.mode == MmmMode::GoTo {
+            self.update_completions();
+        }
+        let listing = self.get_listing();
+        let filter = self.get_match_filter().to_string();
+        self.selected_entry = 0;
+        if filter.is_empty() {
+            self.filtered_current_dir_list = listing
                 .iter()
                 .filter_map(|entry| filter_hidden(entry.clone(), self.show_hidden_files))
                 .map(|entry| MmmScoredDirEntry {
@@ -186,18 +811,18 @@ impl MmmFilesys {
                 })
                 .collect();
         } else {
-            let local_show_hidden: bool = self.show_hidden_files
-                || self
-                    .filter
-                    .chars()
-                    .nth(0)
-                    .map(|c| c == '.')
-                    .unwrap_or(false);
-            let mut filtered_scored: Vec<MmmScoredDirEntry> = self
-                .current_dir_list
+            let local_show_hidden: bool =
+                self.show_hidden_files || filter.chars().nth(0).map(|c| c == '.').unwrap_or(false);
+            let mut filtered_scored: Vec<MmmScoredDirEntry> = listing
                 .iter()
                 .filter_map(|entry| filter_hidden(entry.clone(), local_show_hidden))
-                .filter_map(|entry| filter_and_score(entry.clone(), &self.filter))
+                .filter_map(|entry| filter_and_score(entry.clone(), &filter))
+                .map(|mut scored| {
+                    if self.mode == MmmMode::Jump {
+                        scored.score += self.frecency.get_score_bonus(scored.entry.get_path());
+                    }
+                    scored
+                })
                 .collect();
             filtered_scored.sort_by_key(|entry| entry.score);
             filtered_scored.reverse();
@@ -207,17 +832,239 @@ impl MmmFilesys {
 
     pub fn try_nav_into(&mut self) -> MmmResult<()> {
         if let Some(dir_entry) = self.get_selected_entry() {
-            if let MmmDirEntry::Directory { name: _, path } = &*dir_entry {
-                self.change_directory(path.to_path_buf())?;
+            // Links are entered through their own path so navigation stays logical like cd, or
+            // through the resolved path like cd -P
+            if dir_entry.is_dir_like() {
+                let path = if self.physical_paths {
+                    fs::canonicalize(dir_entry.get_path())?
+                } else {
+                    dir_entry.get_path().to_path_buf()
+                };
+                self.change_directory(path)?;
             }
         }
         Ok(())
     }
 
+    /// Picks the marked entries when picking several, otherwise the selected entry or the current
+    /// directory when nothing is selected. A folder is entered instead when only files are wanted.
+    pub fn pick(&mut self) -> MmmResult<()> {
+        let Some(picker) = self.picker else {
+            return Ok(());
+        };
+        let paths = if picker.multi && !self.marked.is_empty() {
+            self.marked.iter().cloned().collect()
+        } else {
+            match self.get_selected_entry() {
+                Some(dir_entry) if picker.kind == MmmPickKind::Files && dir_entry.is_dir_like() => {
+                    return self.try_nav_into();
+                }
+                Some(dir_entry) => vec![dir_entry.get_path().to_path_buf()],
+                None => vec![self.current_path.clone()],
+            }
+        };
+        if let Some(path) = paths.iter().find(|path| !picker.accepts(path)) {
+            return Err(format!(
+                "{} is not a {}",
+                path.file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy(),
+                picker.get_kind_name()
+            )
+            .into());
+        }
+        self.picked = Some(paths);
+        Ok(())
+    }
+
+    /// The paths picked once Enter has been pressed in a picker
+    pub fn get_picked(&self) -> Option<&[PathBuf]> {
+        self.picked.as_deref()
+    }
+
+    /// Forgets what was picked so picking can carry on
+    pub fn finish_pick(&mut self) {
+        self.picked = None;
+        self.marked.clear();
+    }
+
     pub fn try_nav_back(&mut self) -> MmmResult<()> {
         if let Some(path) = self.current_path.parent() {
             self.change_directory(path.to_path_buf())?;
         }
         Ok(())
     }
+
+    /// Creates a directory in the current path named after the filter
+    pub fn make_dir_from_filter(&mut self) -> MmmResult<()> {
+        let name = self.filter.clone();
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(format!("invalid directory name '{}'", name).into());
+        }
+        let op = make_dir(&self.current_path.join(name))?;
+        self.journal.record(vec![op]);
+        self.change_directory(self.current_path.clone())
+    }
+
+    pub fn toggle_mark_selected(&mut self) {
+        if let Some(dir_entry) = self.get_selected_entry() {
+            let path = dir_entry.get_path().to_path_buf();
+            if !self.marked.remove(&path) {
+                self.marked.insert(path);
+            }
+        }
+    }
+
+    pub fn get_marked(&self) -> &BTreeSet<PathBuf> {
+        &self.marked
+    }
+
+    /// The marked paths if there are any, otherwise the selected entry
+    fn get_targets(&self) -> Vec<PathBuf> {
+        if self.marked.is_empty() {
+            self.get_selected_entry()
+                .map(|dir_entry| vec![dir_entry.get_path().to_path_buf()])
+                .unwrap_or_default()
+        } else {
+            self.marked.iter().cloned().collect()
+        }
+    }
+
+    pub fn trash_selected(&mut self) -> MmmResult<()> {
+        let mut batch = vec![];
+        let mut result = Ok(());
+        for path in self.get_targets() {
+            match trash_path(&path, self.trash_dir.as_deref()) {
+                Ok(op) => batch.push(op),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        self.journal.record(batch);
+        self.marked.clear();
+        self.refresh()?;
+        result
+    }
+
+    pub fn yank_selected(&mut self, cut: bool) {
+        let paths = self.get_targets();
+        if !paths.is_empty() {
+            self.clipboard = Some(MmmClipboard { paths, cut });
+            self.marked.clear();
+        }
+    }
+
+    pub fn get_clipboard(&self) -> Option<&MmmClipboard> {
+        self.clipboard.as_ref()
+    }
+
+    /// Queues a job copying or moving the clipboard contents into the current path
+    pub fn paste(&mut self) {
+        let Some(clipboard) = self.clipboard.take() else {
+            return;
+        };
+        self.jobs.push(
+            clipboard.paths.clone(),
+            self.current_path.clone(),
+            clipboard.cut,
+        );
+        if !clipboard.cut {
+            self.clipboard = Some(clipboard);
+        }
+    }
+
+    /// Records the operations of a finished job and refreshes the listing, returns a summary
+    /// of the finished job if there is one
+    pub fn poll_jobs(&mut self) -> MmmResult<Option<String>> {
+        let Some(outcome) = self.jobs.poll() else {
+            return Ok(None);
+        };
+        let pasted = outcome
+            .ops
+            .iter()
+            .filter(|op| matches!(op, MmmFileOp::Copy { .. } | MmmFileOp::Move { .. }))
+            .count();
+        self.journal.record(outcome.ops);
+        self.refresh()?;
+        match outcome.error {
+            Some(_) if outcome.cancelled => Ok(Some(format!("paste cancelled, {} pasted", pasted))),
+            Some(err) => Err(err),
+            None => Ok(Some(format!("pasted {}", pasted))),
+        }
+    }
+
+    pub fn jobs_active(&self) -> bool {
+        self.jobs.is_active()
+    }
+
+    pub fn get_job_status(&self) -> Option<MmmJobStatus> {
+        self.jobs.get_status()
+    }
+
+    pub fn cancel_jobs(&mut self) {
+        self.jobs.cancel();
+    }
+
+    pub fn cycle_conflict_policy(&mut self) {
+        self.jobs.policy = self.jobs.policy.next();
+    }
+
+    pub fn get_conflict_policy(&self) -> MmmConflictPolicy {
+        self.jobs.policy
+    }
+
+    /// The names offered for bulk renaming, the marked entries in this directory if there are
+    /// any, otherwise everything matching the filter
+    pub fn get_rename_names(&self) -> Vec<String> {
+        let marked_here: Vec<String> = self
+            .current_dir_list
+            .iter()
+            .filter(|entry| self.marked.contains(entry.get_path()))
+            .map(|entry| entry.get_name().to_string())
+            .collect();
+        if marked_here.is_empty() {
+            self.filtered_current_dir_list
+                .iter()
+                .map(|scored| scored.entry.get_name().to_string())
+                .collect()
+        } else {
+            marked_here
+        }
+    }
+
+    pub fn bulk_rename(&mut self, originals: &[String], edited: &str) -> MmmResult<()> {
+        let steps = order_renames(
+            &self.current_path,
+            plan_renames(&self.current_path, originals, edited)?,
+        );
+        let mut batch = vec![];
+        let mut result = Ok(());
+        for (from, to) in steps {
+            match rename_path(&self.current_path.join(from), &self.current_path.join(to)) {
+                Ok(op) => batch.push(op),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        self.journal.record(batch);
+        self.marked.clear();
+        self.refresh()?;
+        result
+    }
+
+    pub fn undo(&mut self) -> MmmResult<()> {
+        let result = self.journal.undo(self.trash_dir.as_deref());
+        self.refresh()?;
+        result
+    }
+
+    pub fn redo(&mut self) -> MmmResult<()> {
+        let result = self.journal.redo(self.trash_dir.as_deref());
+        self.refresh()?;
+        result
+    }
 }
@@ -0,0 +1,554 @@
+use super::dir_entry::format_time;
+use crate::error_type::{MmmError, MmmResult};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// A reversible file operation performed from within mmm
+#[derive(Clone, Debug, PartialEq)]
+pub enum MmmFileOp {
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+        modified: Option<SystemTime>,
+    },
+    Mkdir {
+        path: PathBuf,
+    },
+    Trash {
+        path: PathBuf,
+        trashed_path: PathBuf,
+        info_path: PathBuf,
+    },
+}
+
+impl MmmFileOp {
+    fn revert(&self, trash_dir: Option<&Path>) -> MmmResult<()> {
+        match self {
+            MmmFileOp::Rename { from, to } | MmmFileOp::Move { from, to } => {
+                require_exists(to)?;
+                require_vacant(from)?;
+                move_path(to, from)
+            }
+            MmmFileOp::Copy { to, modified, .. } => {
+                require_exists(to)?;
+                if get_modified(to) != *modified {
+                    return Err(MmmError::Journal(format!(
+                        "{} has been modified since it was copied",
+                        to.display()
+                    )));
+                }
+                // The copy may have been worked on since, so it goes to the trash rather than
+                // being lost for good
+                trash_path(to, trash_dir).map(|_| ())
+            }
+            MmmFileOp::Mkdir { path } => {
+                require_exists(path)?;
+                fs::remove_dir(path).map_err(|err| {
+                    MmmError::Journal(format!("cannot remove {}, {}", path.display(), err))
+                })
+            }
+            MmmFileOp::Trash {
+                path,
+                trashed_path,
+                info_path,
+            } => {
+                require_exists(trashed_path)?;
+                require_vacant(path)?;
+                move_path(trashed_path, path)?;
+                let _ = fs::remove_file(info_path);
+                Ok(())
+            }
+        }
+    }
+
+    fn reapply(&self, trash_dir: Option<&Path>) -> MmmResult<MmmFileOp> {
+        match self {
+            MmmFileOp::Rename { from, to } => {
+                require_exists(from)?;
+                rename_path(from, to)
+            }
+            MmmFileOp::Move { from, to } => {
+                require_exists(from)?;
+                move_to(from, to)
+            }
+            MmmFileOp::Copy { from, to, .. } => {
+                require_exists(from)?;
+                copy_to(from, to)
+            }
+            MmmFileOp::Mkdir { path } => {
+                require_vacant(path)?;
+                make_dir(path)
+            }
+            MmmFileOp::Trash { path, .. } => {
+                require_exists(path)?;
+                trash_path(path, trash_dir)
+            }
+        }
+    }
+}
+
+/// Paths yanked or cut, waiting to be pasted into another directory
+#[derive(Clone, Debug, PartialEq)]
+pub struct MmmClipboard {
+    pub paths: Vec<PathBuf>,
+    pub cut: bool,
+}
+
+/// Undo and redo stacks of the file operations performed this session, grouped into batches
+/// that are undone and redone together
+#[derive(Debug, Default)]
+pub struct MmmJournal {
+    undo_stack: Vec<Vec<MmmFileOp>>,
+    redo_stack: Vec<Vec<MmmFileOp>>,
+}
+
+impl MmmJournal {
+    pub fn record(&mut self, batch: Vec<MmmFileOp>) {
+        if !batch.is_empty() {
+            self.undo_stack.push(batch);
+            self.redo_stack.clear();
+        }
+    }
+
+    /// Reverts the last batch, an operation that can no longer be safely reverted is dropped.
+    /// Undoing a copy trashes it into `trash_dir`.
+    pub fn undo(&mut self, trash_dir: Option<&Path>) -> MmmResult<()> {
+        let batch = self
+            .undo_stack
+            .pop()
+            .ok_or(MmmError::Journal("nothing to undo".to_string()))?;
+        let mut reverted = vec![];
+        let mut result = Ok(());
+        for op in batch.into_iter().rev() {
+            if let Err(err) = op.revert(trash_dir) {
+                result = Err(err);
+                break;
+            }
+            reverted.push(op);
+        }
+        if !reverted.is_empty() {
+            reverted.reverse();
+            self.redo_stack.push(reverted);
+        }
+        result
+    }
+
+    /// Reapplies the last undone batch, trashing again into `trash_dir`
+    pub fn redo(&mut self, trash_dir: Option<&Path>) -> MmmResult<()> {
+        let batch = self
+            .redo_stack
+            .pop()
+            .ok_or(MmmError::Journal("nothing to redo".to_string()))?;
+        let mut applied = vec![];
+        let mut result = Ok(());
+        for op in batch {
+            match op.reapply(trash_dir) {
+                Ok(new_op) => applied.push(new_op),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        if !applied.is_empty() {
+            self.undo_stack.push(applied);
+        }
+        result
+    }
+}
+
+fn require_exists(path: &Path) -> MmmResult<()> {
+    if fs::symlink_metadata(path).is_err() {
+        return Err(MmmError::Journal(format!(
+            "{} no longer exists",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+fn require_vacant(path: &Path) -> MmmResult<()> {
+    if fs::symlink_metadata(path).is_ok() {
+        return Err(MmmError::Journal(format!(
+            "{} already exists",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+pub fn require_not_inside(from: &Path, to: &Path) -> MmmResult<()> {
+    if to.starts_with(from) {
+        return Err(format!("cannot place {} inside itself", from.display()).into());
+    }
+    Ok(())
+}
+
+/// The latest modification time of a path or anything inside it, as editing a file deep in a
+/// copied directory leaves the directory's own time alone
+pub fn get_modified(path: &Path) -> Option<SystemTime> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    let mut latest = metadata.modified().ok()?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path).ok()? {
+            latest = latest.max(get_modified(&entry.ok()?.path())?);
+        }
+    }
+    Some(latest)
+}
+
+/// Picks a name in `dir` that does not collide with an existing entry, `name (1).ext` style
+pub fn get_unique_path(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if fs::symlink_metadata(&candidate).is_err() {
+        return candidate;
+    }
+    let (stem, extension) = match name.rfind('.') {
+        Some(idx) if idx > 0 => name.split_at(idx),
+        _ => (name, ""),
+    };
+    (1..)
+        .map(|i| dir.join(format!("{} ({}){}", stem, i, extension)))
+        .find(|path| fs::symlink_metadata(path).is_err())
+        .expect("unbounded iterator ended")
+}
+
+pub fn copy_path(from: &Path, to: &Path) -> MmmResult<()> {
+    copy_path_with_progress(from, to, &|_, _| Ok(()))
+}
+
+/// Recursively copies a path, calling `on_progress` with the bytes and entries copied since the
+/// last call. An error from `on_progress` aborts the copy, leaving a partial copy behind.
+pub fn copy_path_with_progress(
+    from: &Path,
+    to: &Path,
+    on_progress: &dyn Fn(u64, u64) -> MmmResult<()>,
+) -> MmmResult<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_path_with_progress(&entry.path(), &to.join(entry.file_name()), on_progress)?;
+        }
+        fs::set_permissions(to, metadata.permissions())?;
+    } else if metadata.file_type().is_symlink() {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(fs::read_link(from)?, to)?;
+        #[cfg(not(unix))]
+        fs::copy(from, to)?;
+    } else {
+        let mut reader = File::open(from)?;
+        let mut writer = OpenOptions::new().write(true).create_new(true).open(to)?;
+        let mut buf = vec![0; 1 << 16];
+        loop {
+            let len = reader.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            writer.write_all(&buf[..len])?;
+            on_progress(len as u64, 0)?;
+        }
+        fs::set_permissions(to, metadata.permissions())?;
+    }
+    on_progress(0, 1)
+}
+
+pub fn remove_path(path: &Path) -> MmmResult<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Whether a rename failed because the source and destination are on different filesystems
+pub fn crosses_devices(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    let code = libc::EXDEV;
+    // ERROR_NOT_SAME_DEVICE
+    #[cfg(not(unix))]
+    let code = 17;
+    err.raw_os_error() == Some(code)
+}
+
+/// Renames a path, falling back to copy and remove when crossing filesystems
+pub fn move_path(from: &Path, to: &Path) -> MmmResult<()> {
+    match fs::rename(from, to) {
+        Err(err) if crosses_devices(&err) => {
+            copy_path(from, to)?;
+            remove_path(from)
+        }
+        result => Ok(result?),
+    }
+}
+
+pub fn rename_path(from: &Path, to: &Path) -> MmmResult<MmmFileOp> {
+    require_vacant(to)?;
+    fs::rename(from, to)?;
+    Ok(MmmFileOp::Rename {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+    })
+}
+
+pub fn move_to(from: &Path, to: &Path) -> MmmResult<MmmFileOp> {
+    require_vacant(to)?;
+    require_not_inside(from, to)?;
+    move_path(from, to)?;
+    Ok(MmmFileOp::Move {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+    })
+}
+
+pub fn copy_to(from: &Path, to: &Path) -> MmmResult<MmmFileOp> {
+    require_vacant(to)?;
+    require_not_inside(from, to)?;
+    copy_path(from, to)?;
+    Ok(MmmFileOp::Copy {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+        modified: get_modified(to),
+    })
+}
+
+pub fn make_dir(path: &Path) -> MmmResult<MmmFileOp> {
+    fs::create_dir(path)?;
+    Ok(MmmFileOp::Mkdir {
+        path: path.to_path_buf(),
+    })
+}
+
+/// Moves a path to the freedesktop.org trash in `trash_dir`, writing the matching `.trashinfo`
+/// file. There is nowhere to trash to without a data directory.
+pub fn trash_path(path: &Path, trash_dir: Option<&Path>) -> MmmResult<MmmFileOp> {
+    let trash_dir = trash_dir.ok_or(format!(
+        "cannot trash {}, no data directory",
+        path.display()
+    ))?;
+    let files_dir = trash_dir.join("files");
+    let info_dir = trash_dir.join("info");
+    fs::create_dir_all(&files_dir)?;
+    fs::create_dir_all(&info_dir)?;
+    let name = path
+        .file_name()
+        .ok_or(format!("cannot trash {}", path.display()))?
+        .to_string_lossy()
+        .to_string();
+    let abs_path = std::path::absolute(path)?;
+    // Reserve a name in the trash by creating the info file first, as the spec recommends
+    let (trashed_path, info_path, mut info_file) = (0..)
+        .map(|i| {
+            let trash_name = if i == 0 {
+                name.clone()
+            } else {
+                format!("{}.{}", name, i)
+            };
+            let info_path = info_dir.join(format!("{}.trashinfo", trash_name));
+            (files_dir.join(trash_name), info_path)
+        })
+        .find_map(|(trashed_path, info_path)| {
+            if fs::symlink_metadata(&trashed_path).is_ok() {
+                return None;
+            }
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+            {
+                Ok(file) => Some(Ok((trashed_path, info_path, file))),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => None,
+                Err(err) => Some(Err(err)),
+            }
+        })
+        .expect("unbounded iterator ended")?;
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode_path(&abs_path),
+        format_time(SystemTime::now(), 'T')
+    );
+    let result = info_file
+        .write_all(info.as_bytes())
+        .map_err(MmmError::from)
+        .and_then(|_| move_path(path, &trashed_path));
+    if let Err(err) = result {
+        let _ = fs::remove_file(&info_path);
+        return Err(err);
+    }
+    Ok(MmmFileOp::Trash {
+        path: abs_path,
+        trashed_path,
+        info_path,
+    })
+}
+
+fn percent_encode_path(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+    #[cfg(not(unix))]
+    let bytes = path.to_string_lossy().as_bytes().to_vec();
+    bytes
+        .into_iter()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{make_data_home, make_tree};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn undoing_a_copy_trashes_it_and_redo_copies_again() {
+        let root = make_tree("ops-copy", &["dest"], &["one.txt"]);
+        let data_home = make_data_home();
+        let trash_dir = data_home.join("Trash");
+        fs::write(root.join("one.txt"), "one").unwrap();
+        let mut journal = MmmJournal::default();
+        journal.record(vec![copy_to(
+            &root.join("one.txt"),
+            &root.join("dest/one.txt"),
+        )
+        .unwrap()]);
+
+        journal.undo(Some(&trash_dir)).unwrap();
+        assert!(!root.join("dest/one.txt").exists());
+        assert_eq!(
+            fs::read_to_string(trash_dir.join("files/one.txt")).unwrap(),
+            "one"
+        );
+        assert!(trash_dir.join("info/one.txt.trashinfo").exists());
+
+        journal.redo(Some(&trash_dir)).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("dest/one.txt")).unwrap(),
+            "one"
+        );
+        assert!(root.join("one.txt").exists());
+    }
+
+    #[test]
+    fn undoing_a_modified_copy_is_refused() {
+        let root = make_tree("ops-copy-modified", &[], &["one.txt"]);
+        let data_home = make_data_home();
+        let trash_dir = data_home.join("Trash");
+        let mut journal = MmmJournal::default();
+        journal.record(vec![
+            copy_to(&root.join("one.txt"), &root.join("two.txt")).unwrap()
+        ]);
+        File::options()
+            .write(true)
+            .open(root.join("two.txt"))
+            .unwrap()
+            .set_modified(UNIX_EPOCH)
+            .unwrap();
+        assert!(journal.undo(Some(&trash_dir)).is_err());
+        assert!(root.join("two.txt").exists());
+    }
+
+    #[test]
+    fn undoing_a_copy_modified_deep_inside_is_refused() {
+        let root = make_tree("ops-copy-deep", &["from/inner"], &["from/inner/one.txt"]);
+        let data_home = make_data_home();
+        let trash_dir = data_home.join("Trash");
+        let mut journal = MmmJournal::default();
+        journal.record(vec![copy_to(&root.join("from"), &root.join("to")).unwrap()]);
+        File::options()
+            .write(true)
+            .open(root.join("to/inner/one.txt"))
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(journal.undo(Some(&trash_dir)).is_err());
+        assert!(root.join("to/inner/one.txt").exists());
+    }
+
+    #[test]
+    fn trashing_round_trips_through_undo_and_redo() {
+        let root = make_tree("ops-trash", &["a dir/inner"], &["a dir/file"]);
+        let data_home = make_data_home();
+        let trash_dir = data_home.join("Trash");
+        let op = trash_path(&root.join("a dir"), Some(&trash_dir)).unwrap();
+        let MmmFileOp::Trash {
+            trashed_path,
+            info_path,
+            ..
+        } = &op
+        else {
+            panic!("trashing gave {:?}", op);
+        };
+        assert!(!root.join("a dir").exists());
+        assert!(trashed_path.join("inner").is_dir());
+        let info = fs::read_to_string(info_path).unwrap();
+        assert!(info.starts_with("[Trash Info]\n"));
+        assert!(info.contains(&format!(
+            "Path={}\n",
+            percent_encode_path(&root.join("a dir"))
+        )));
+
+        let mut journal = MmmJournal::default();
+        journal.record(vec![op.clone()]);
+        journal.undo(Some(&trash_dir)).unwrap();
+        assert!(root.join("a dir/file").exists());
+        assert!(!info_path.exists());
+
+        journal.redo(Some(&trash_dir)).unwrap();
+        assert!(!root.join("a dir").exists());
+        assert!(trashed_path.join("file").exists());
+    }
+
+    #[test]
+    fn trashing_the_same_name_twice_keeps_both() {
+        let root = make_tree("ops-trash-twice", &[], &["same"]);
+        let data_home = make_data_home();
+        let trash_dir = data_home.join("Trash");
+        trash_path(&root.join("same"), Some(&trash_dir)).unwrap();
+        fs::write(root.join("same"), "").unwrap();
+        trash_path(&root.join("same"), Some(&trash_dir)).unwrap();
+        assert!(trash_dir.join("files/same").exists());
+        assert!(trash_dir.join("files/same.1").exists());
+        assert!(trash_dir.join("info/same.1.trashinfo").exists());
+        assert!(trash_path(&root.join("none"), None).is_err());
+    }
+
+    #[test]
+    fn paths_are_percent_encoded() {
+        assert_eq!(
+            percent_encode_path(Path::new("/home/me/a b/ünï%.txt")),
+            "/home/me/a%20b/%C3%BCn%C3%AF%25.txt"
+        );
+        assert_eq!(percent_encode_path(Path::new("/a-b_c.~d")), "/a-b_c.~d");
+    }
+
+    #[test]
+    fn deletion_dates_are_in_trashinfo_format() {
+        let time = UNIX_EPOCH + Duration::from_secs(1704067200 + 3661);
+        assert_eq!(format_time(time, 'T'), "2024-01-01T01:01:01");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951782400 + 86399);
+        assert_eq!(format_time(leap_day, 'T'), "2000-02-29T23:59:59");
+    }
+}
@@ -0,0 +1,37 @@
+use std::path::Path;
+
+/// Which entries a picker hands back
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MmmPickKind {
+    #[default]
+    Any,
+    Files,
+    Dirs,
+}
+
+/// How entries are picked when mmm is run as a chooser for another program
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MmmPicker {
+    pub kind: MmmPickKind,
+    /// Pick every marked entry rather than just the selected one
+    pub multi: bool,
+}
+
+impl MmmPicker {
+    /// Whether `path` is the kind of entry being picked, symlinks count as what they point to
+    pub fn accepts(&self, path: &Path) -> bool {
+        match self.kind {
+            MmmPickKind::Any => true,
+            MmmPickKind::Files => !path.is_dir(),
+            MmmPickKind::Dirs => path.is_dir(),
+        }
+    }
+
+    pub fn get_kind_name(&self) -> &str {
+        match self.kind {
+            MmmPickKind::Any => "entry",
+            MmmPickKind::Files => "file",
+            MmmPickKind::Dirs => "directory",
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::error_type::MmmResult;
+use std::{
+    ffi::{OsStr, OsString},
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
+
+/// What the placeholders in a user command are filled in from, taken when the command list is
+/// opened so it is what the user was looking at
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MmmCommandContext {
+    pub selected: Option<PathBuf>,
+    /// The marked paths, or the selected entry when nothing is marked
+    pub targets: Vec<PathBuf>,
+    pub dir: PathBuf,
+    pub filter: String,
+}
+
+impl MmmCommandContext {
+    /// Fills in `{}` with the selected path, `{marked}` with the marked paths, `{dir}` with the
+    /// current directory and `{filter}` with the filter, each quoted for the shell
+    pub fn expand(&self, template: &str) -> OsString {
+        expand_placeholders(template, |name| match name {
+            "" => Some(self.selected.as_deref().map(quote_path).unwrap_or_default()),
+            "marked" => Some(quote_paths(&self.targets)),
+            "dir" => Some(quote_path(&self.dir)),
+            "filter" => Some(quote(&self.filter).into()),
+            _ => None,
+        })
+    }
+}
+
+/// Quotes text so the shell passes it through as a single argument
+pub fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Quotes a path the same way, keeping names that aren't valid UTF-8 byte for byte
+#[cfg(unix)]
+pub fn quote_path(path: &Path) -> OsString {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let mut quoted = vec![b'\''];
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'\'' => quoted.extend_from_slice(br"'\''"),
+            _ => quoted.push(byte),
+        }
+    }
+    quoted.push(b'\'');
+    OsString::from_vec(quoted)
+}
+
+#[cfg(not(unix))]
+pub fn quote_path(path: &Path) -> OsString {
+    quote(&path.to_string_lossy()).into()
+}
+
+/// Quotes each path, separated by spaces
+pub fn quote_paths(paths: &[PathBuf]) -> OsString {
+    let mut quoted = OsString::new();
+    for (index, path) in paths.iter().enumerate() {
+        if index > 0 {
+            quoted.push(" ");
+        }
+        quoted.push(quote_path(path));
+    }
+    quoted
+}
+
+/// Replaces each `{name}` in a command template with what `lookup` gives for `name`, `{}` looks up
+/// an empty name. Unknown placeholders are left as they are.
+pub fn expand_placeholders(template: &str, lookup: impl Fn(&str) -> Option<OsString>) -> OsString {
+    let mut expanded = OsString::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after
+            .find('}')
+            .and_then(|end| Some((lookup(&after[..end])?, end)))
+        {
+            Some((value, end)) => {
+                expanded.push(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                expanded.push("{");
+                rest = after;
+            }
+        }
+    }
+    expanded.push(rest);
+    expanded
+}
+
+/// Runs a command line with `sh` in `cwd` on the terminal, then waits for Enter so its output
+/// can be read before the display comes back. The display must be stopped first.
+pub fn run_shell_command(command: &OsStr, cwd: &Path) -> MmmResult<ExitStatus> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(cwd)
+        .status()?;
+    let mut stdout = io::stdout();
+    write!(stdout, "\npress Enter to return to mmm")?;
+    stdout.flush()?;
+    io::stdin().lock().read_line(&mut String::new())?;
+    Ok(status)
+}
+
+/// Runs a command line with `sh` in `cwd` without a terminal, on tokio so the display keeps
+/// going while it runs
+pub async fn run_quiet_shell_command(command: OsString, cwd: PathBuf) -> io::Result<ExitStatus> {
+    tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+}
+
+/// How many mmm subshells deep this process is, from `MMM_LEVEL`
+pub fn get_shell_level() -> u32 {
+    parse_shell_level(std::env::var("MMM_LEVEL").ok().as_deref())
+}
+
+/// Anything but a count of shells is taken as not being in one
+fn parse_shell_level(level: Option<&str>) -> u32 {
+    level.and_then(|level| level.parse().ok()).unwrap_or(0)
+}
+
+/// Runs the user's `$SHELL` in `cwd` until it exits, with `MMM_LEVEL` counting how deep the
+/// shells are nested. How the shell exits is its own business, only failing to start it is an
+/// error. The display must be stopped before calling this.
+pub fn run_subshell(cwd: &Path) -> MmmResult<()> {
+    let shell = std::env::var("SHELL").unwrap_or("sh".to_string());
+    let level = get_shell_level() + 1;
+    Command::new(&shell)
+        .current_dir(cwd)
+        .env("MMM_LEVEL", level.to_string())
+        .status()
+        .map_err(|err| format!("cannot run {}, {}", shell, err))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_quoted_for_the_shell() {
+        assert_eq!(quote_path(Path::new("/a b/it's")), r"'/a b/it'\''s'");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn paths_that_are_not_utf8_are_quoted_as_they_are() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"/caf\xe9's"));
+        assert_eq!(quote_path(path).as_bytes(), b"'/caf\xe9'\\''s'".as_slice());
+    }
+
+    #[test]
+    fn shell_levels_are_parsed() {
+        assert_eq!(parse_shell_level(None), 0);
+        assert_eq!(parse_shell_level(Some("2")), 2);
+        assert_eq!(parse_shell_level(Some("")), 0);
+        assert_eq!(parse_shell_level(Some("-1")), 0);
+        assert_eq!(parse_shell_level(Some("deep")), 0);
+    }
+
+    #[test]
+    fn placeholders_are_replaced() {
+        let lookup = |name: &str| match name {
+            "" => Some("'x'".into()),
+            "dir" => Some("'/d'".into()),
+            _ => None,
+        };
+        assert_eq!(
+            expand_placeholders("cp {} {dir} {nope} {", lookup),
+            "cp 'x' '/d' {nope} {"
+        );
+    }
+
+    #[test]
+    fn command_context_fills_every_placeholder() {
+        let context = MmmCommandContext {
+            selected: Some("/d/a".into()),
+            targets: vec!["/d/a".into(), "/d/b c".into()],
+            dir: "/d".into(),
+            filter: "new".into(),
+        };
+        assert_eq!(
+            context.expand("tar cf {filter}.tar -C {dir} {marked} && echo {}"),
+            "tar cf 'new'.tar -C '/d' '/d/a' '/d/b c' && echo '/d/a'"
+        );
+        assert_eq!(MmmCommandContext::default().expand("ls {}"), "ls ");
+    }
+}
@@ -0,0 +1,20 @@
+use crate::error_type::MmmResult;
+use std::path::PathBuf;
+
+/// Resolves `$XDG_DATA_HOME`, falling back to `$HOME/.local/share` as the spec requires
+pub fn get_data_home() -> MmmResult<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .ok_or("cannot locate data directory, neither $XDG_DATA_HOME or $HOME are set".into())
+}
+
+/// Resolves `$XDG_CONFIG_HOME`, falling back to `$HOME/.config` as the spec requires
+pub fn get_config_home() -> MmmResult<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .ok_or("cannot locate config directory, neither $XDG_CONFIG_HOME or $HOME are set".into())
+}
@@ -1,93 +1,225 @@
-use std::{fs::File, io::Write, path::PathBuf, time::Duration};
-
-use crossterm::event::EventStream;
-use doubuff::{
-    buffer::TerminalBuffer,
-    helpers::{start_display, stop_display},
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
 };
+
+use app::{MmmApp, MmmSignal, MmmTerminalEvents};
+use args::{MmmArgs, USAGE};
+use config::MmmConfig;
+use doubuff::buffer::TerminalBuffer;
 use error_type::MmmResult;
-use filesystem::MmmFilesys;
-use futures::{select, FutureExt, StreamExt};
+use filesystem::{
+    dir_entry::get_working_dir,
+    shell::get_shell_level,
+    xdg::{get_config_home, get_data_home},
+};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver};
 use terminal::{
-    events::{
-        decode_crossterm_event, get_state_update_type, process_state_update, MmmStateUpdateType,
+    canvas::MmmInlineCanvas,
+    display::{
+        start_mmm_display, stop_mmm_display, stop_started_display, take_over_stdout, MmmDisplay,
     },
-    layout::MmmLayout,
+    layout::get_terminal_size,
+    status::MmmStatus,
 };
-use tokio::time::sleep;
 
+mod app;
+mod args;
+mod config;
 mod debug;
 mod error_type;
 mod filesystem;
 mod terminal;
+#[cfg(test)]
+mod test_util;
 
-async fn mmm() -> MmmResult<PathBuf> {
-    let initial_path = std::env::current_dir()?;
-    let mut layout = MmmLayout::new()?;
-    let mut filesys = MmmFilesys::from_path(initial_path)?;
-    let mut term_buffer = TerminalBuffer::new(layout.term_size);
-    let mut event_stream = EventStream::new();
-    let mut one_time_trigger = Box::pin(async {}.fuse());
+/// Exit code when mmm stops because of an error
+const ERROR_CODE: i32 = 1;
+/// Exit code for bad command line options
+const USAGE_CODE: i32 = 2;
+/// Exit code after a panic, the same as Rust's own
+const PANIC_CODE: i32 = 101;
+/// Exit code when a picker is closed without picking anything, the same as fzf
+const PICK_CANCELLED_CODE: i32 = 130;
 
-    loop {
-        // Wait for a year before updating, just a placeholder for interrupting logic
-        let mut timer = Box::pin(sleep(Duration::from_secs(31536000))).fuse();
-        let mut terminal_event_future = event_stream.next().fuse();
-        let mut terminal_event = None;
+/// Where mmm was left, and what was picked when running as a picker
+struct MmmExit {
+    path: PathBuf,
+    picked: Option<Vec<PathBuf>>,
+    /// How many picks were handed to the on select command
+    on_select_picks: usize,
+    /// The signal mmm was stopped by, if it was
+    stopped_by: Option<MmmSignal>,
+}
 
-        // Wait for an event, the only async section, this needs to be async for the event stream to work
-        select! {
-            terminal_event_local = terminal_event_future => {
-                if let Some(te) = terminal_event_local {
-                    terminal_event = Some(te?);
-                }
-            },
-            _ = one_time_trigger => {
-                    terminal_event = Some(crossterm::event::Event::Resize(
-                        layout.term_size.col,
-                        layout.term_size.row
-                    ))
-                },
-            _ = timer => {},
+/// Runs mmm on a started display, leaving it as it was last laid out so it can be stopped
+async fn mmm(
+    args: &MmmArgs,
+    display: &mut MmmDisplay,
+    signals: UnboundedReceiver<MmmSignal>,
+) -> MmmResult<MmmExit> {
+    let mut status = MmmStatus::default();
+    let level = get_shell_level();
+    if level > 0 {
+        status.error(format!(
+            "already in an mmm shell (MMM_LEVEL={}), exit it to go back",
+            level
+        ));
+    }
+    let config = get_config_home()
+        .and_then(|config_home| MmmConfig::load(&config_home))
+        .unwrap_or_else(|err| {
+            status.error(format!("config ignored, {}", err));
+            MmmConfig::default()
+        });
+    let initial_path = get_working_dir(config.physical_paths)?;
+    let mut app = MmmApp::new(
+        initial_path,
+        display.fit(get_terminal_size()?),
+        &config,
+        get_data_home().ok().as_deref(),
+        args.picker,
+        status,
+    )?;
+    app.on_select = args.on_select.clone();
+    app.signals = Some(signals);
+    app.layout.display = *display;
+    let mut events = MmmTerminalEvents::default();
+    let run = match display {
+        MmmDisplay::Inline { .. } => app.run(&mut events, &mut MmmInlineCanvas::default()).await,
+        MmmDisplay::Fullscreen => {
+            let mut term_buffer = TerminalBuffer::new(app.layout.term_size);
+            app.run(&mut events, &mut term_buffer).await
         }
+    };
+    // Resizing and suspending move an inline display
+    *display = app.layout.display;
+    run?;
+    app.filesys.record_exit();
+    Ok(MmmExit {
+        path: app.filesys.get_current_path().to_path_buf(),
+        picked: app.filesys.get_picked().map(|picked| picked.to_vec()),
+        on_select_picks: app.get_on_select_picks(),
+        stopped_by: app.stopped_by,
+    })
+}
 
-        // State update logic
-        let state_update_option = decode_crossterm_event(terminal_event)
-            .and_then(|event| get_state_update_type(event, &filesys));
-        if state_update_option.is_none() {
-            continue;
-        }
-        let draw_ops = match state_update_option.expect("illegal state_update_option state") {
-            MmmStateUpdateType::Exit => {
+/// Prints the picked paths for whatever ran mmm, each followed by a newline or a NUL
+fn print_picked(output: &mut impl Write, picked: &[PathBuf], print0: bool) -> std::io::Result<()> {
+    let separator: &[u8] = if print0 { b"\0" } else { b"\n" };
+    for path in picked {
+        output.write_all(path.as_os_str().as_encoded_bytes())?;
+        output.write_all(separator)?;
+    }
+    output.flush()
+}
+
+/// Gives the terminal back before a panic message is printed, so it is readable and the shell is
+/// usable afterwards. A panic on any thread ends mmm as the display can't be trusted after it.
+fn restore_display_on_panic() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = stop_started_display();
+        default_hook(info);
+        eprintln!("mmm crashed, this is a bug, please report it with the message above");
+        std::process::exit(PANIC_CODE)
+    }));
+}
+
+/// Forwards SIGTERM, and SIGHUP when the terminal goes away, to the main loop, which stops so the
+/// terminal can be given back before exiting
+#[cfg(unix)]
+fn forward_stop_signals() -> std::io::Result<UnboundedReceiver<MmmSignal>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    let (sender, receiver) = unbounded();
+    tokio::spawn(async move {
+        loop {
+            let signal = tokio::select! {
+                _ = terminate.recv() => MmmSignal { name: "SIGTERM", number: libc::SIGTERM },
+                _ = hangup.recv() => MmmSignal { name: "SIGHUP", number: libc::SIGHUP },
+            };
+            if sender.unbounded_send(signal).is_err() {
                 break;
             }
-            state_update => process_state_update(state_update, &mut layout, &mut filesys)?,
-        };
-
-        // Rendering logic
-        draw_ops.draw(&mut term_buffer, &filesys, &layout)?;
-        term_buffer.flush()?;
-    }
+        }
+    });
+    Ok(receiver)
+}
 
-    Ok(filesys.get_current_path().to_path_buf())
+#[cfg(not(unix))]
+fn forward_stop_signals() -> std::io::Result<UnboundedReceiver<MmmSignal>> {
+    Ok(unbounded().1)
 }
 
 #[tokio::main]
 async fn main() {
-    start_display().expect("error starting display");
-    let mmm_result = mmm().await;
-    stop_display().expect("error stopping display");
+    let args = MmmArgs::parse(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("mmm: {}\n\n{}", err, USAGE);
+        std::process::exit(USAGE_CODE)
+    });
+    if args.help {
+        print!("{}", USAGE);
+        std::process::exit(0)
+    }
+    let original_stdout = take_over_stdout().expect("error opening the terminal");
+    let signals = forward_stop_signals().expect("error handling signals");
+    let mut display = MmmDisplay::new(args.height);
+    start_mmm_display(&mut display).expect("error starting display");
+    restore_display_on_panic();
+    let mmm_result = mmm(&args, &mut display, signals).await;
+    stop_mmm_display(display).expect("error stopping display");
     match mmm_result {
-        Ok(path) => {
-            let file_path = "/tmp/mmm.path";
-            let mut file = File::create(file_path).expect("Failed to create or open the temp file");
-            file.write_all(path.to_string_lossy().as_bytes())
-                .expect("Failed to write to temp file");
+        // Exit the way shells report a process killed by the signal
+        Ok(MmmExit {
+            stopped_by: Some(signal),
+            ..
+        }) => {
+            eprintln!("mmm stopped by {}", signal.name);
+            std::process::exit(128 + signal.number)
+        }
+        Ok(MmmExit {
+            path,
+            picked,
+            on_select_picks,
+            stopped_by: None,
+        }) => {
+            // Pickers only say where they were left when asked to
+            if args.picker.is_none() || args.cwd_file.is_some() {
+                let file_path = args
+                    .cwd_file
+                    .as_deref()
+                    .unwrap_or(Path::new("/tmp/mmm.path"));
+                let mut file =
+                    File::create(file_path).expect("Failed to create or open the temp file");
+                file.write_all(path.to_string_lossy().as_bytes())
+                    .expect("Failed to write to temp file");
+            }
+            // Picks were already handed to the on select command as they were made, it only
+            // matters whether there were any
+            if args.on_select.is_some() && on_select_picks == 0 {
+                std::process::exit(PICK_CANCELLED_CODE)
+            }
+            if args.picker.is_some() && args.on_select.is_none() {
+                let Some(picked) = picked else {
+                    std::process::exit(PICK_CANCELLED_CODE)
+                };
+                let written = match (&args.chooser_file, original_stdout) {
+                    (Some(file_path), _) => File::create(file_path)
+                        .and_then(|mut file| print_picked(&mut file, &picked, args.print0)),
+                    (None, Some(mut file)) => print_picked(&mut file, &picked, args.print0),
+                    (None, None) => print_picked(&mut std::io::stdout(), &picked, args.print0),
+                };
+                written.expect("Failed to write the picked paths");
+            }
             std::process::exit(0)
         }
         Err(err) => {
             eprintln!("An error ocurred, {}", err);
-            std::process::exit(1)
+            std::process::exit(ERROR_CODE)
         }
     }
 }
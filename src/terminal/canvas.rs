@@ -0,0 +1,274 @@
+use std::io::{stdout, Write};
+
+use crossterm::{
+    cursor::MoveTo,
+    queue,
+    style::{Attribute, Color, Colors, Print, ResetColor, SetAttribute, SetColors},
+};
+use doubuff::{
+    boxes::TerminalBoxes,
+    buffer::TerminalBuffer,
+    styled_char::{add_style_to_string, StyledChar},
+};
+use terminal_vec2::{vec2, Vec2};
+
+use crate::error_type::MmmResult;
+
+/// Something frames are drawn onto, the terminal or an in memory grid when running headless.
+/// Whatever is drawn stays until it is drawn over or cleared, so only damaged parts need
+/// repainting.
+pub trait MmmCanvas {
+    /// Throws away everything drawn so far and starts a blank frame of `size`, shown from
+    /// terminal row `origin`
+    fn clear(&mut self, size: Vec2, origin: u16);
+    fn move_cursor(&mut self, pos: Vec2) -> MmmResult<&mut Self>;
+    fn styled_print(&mut self, chars: Vec<StyledChar>) -> MmmResult<&mut Self>;
+    /// Draws the thin outline of each `(pos, size)` box, joining lines where they meet
+    fn draw_boxes(&mut self, term_size: Vec2, boxes: &[(Vec2, Vec2)]) -> MmmResult<&mut Self>;
+    /// Shows the frame drawn since the last flush
+    fn flush(&mut self) -> MmmResult<()>;
+
+    /// Fills the `size` cells from `pos` with `c`, clearing whatever was drawn there
+    fn fill(&mut self, pos: Vec2, size: Vec2, c: char) -> MmmResult<()> {
+        let line = c.to_string().repeat(size.col as usize);
+        for row in 0..size.row {
+            self.move_cursor(pos + vec2!(0, row)?)?
+                .styled_print(add_style_to_string(
+                    &line,
+                    Color::Reset,
+                    Color::Reset,
+                    false,
+                ))?;
+        }
+        Ok(())
+    }
+}
+
+impl MmmCanvas for TerminalBuffer {
+    fn clear(&mut self, size: Vec2, _origin: u16) {
+        *self = TerminalBuffer::new(size);
+    }
+
+    fn move_cursor(&mut self, pos: Vec2) -> MmmResult<&mut Self> {
+        Ok(TerminalBuffer::move_cursor(self, pos)?)
+    }
+
+    fn styled_print(&mut self, chars: Vec<StyledChar>) -> MmmResult<&mut Self> {
+        Ok(TerminalBuffer::styled_print(self, chars)?)
+    }
+
+    fn draw_boxes(&mut self, term_size: Vec2, boxes: &[(Vec2, Vec2)]) -> MmmResult<&mut Self> {
+        let mut terminal_boxes = TerminalBoxes::new_thin(term_size);
+        for (pos, size) in boxes {
+            terminal_boxes.add_box(*pos, *size);
+        }
+        Ok(self.draw_box(terminal_boxes)?)
+    }
+
+    fn flush(&mut self) -> MmmResult<()> {
+        Ok(TerminalBuffer::flush(self)?)
+    }
+}
+
+/// The line character for each cell of a `size` grid that the outlines of `boxes` pass through,
+/// joining lines where they meet
+fn get_box_lines(size: Vec2, boxes: &[(Vec2, Vec2)]) -> Vec<Option<char>> {
+    // Collect which directions each cell's lines run in, then pick the character joining them
+    const UP: u8 = 1;
+    const DOWN: u8 = 2;
+    const LEFT: u8 = 4;
+    const RIGHT: u8 = 8;
+    let mut lines = vec![0u8; size.col as usize * size.row as usize];
+    let mut add = |col: u16, row: u16, dirs: u8| {
+        if col < size.col && row < size.row {
+            lines[row as usize * size.col as usize + col as usize] |= dirs;
+        }
+    };
+    for (pos, size) in boxes {
+        let (left, top) = (pos.col, pos.row);
+        let right = left + size.col.saturating_sub(1);
+        let bottom = top + size.row.saturating_sub(1);
+        for col in left..=right {
+            let horizontal = if col == left { RIGHT } else { LEFT | RIGHT }
+                & if col == right { LEFT } else { LEFT | RIGHT };
+            add(col, top, horizontal);
+            add(col, bottom, horizontal);
+        }
+        for row in top..=bottom {
+            let vertical = if row == top { DOWN } else { UP | DOWN }
+                & if row == bottom { UP } else { UP | DOWN };
+            add(left, row, vertical);
+            add(right, row, vertical);
+        }
+    }
+    lines
+        .into_iter()
+        .map(|dirs| match dirs {
+            0 => None,
+            d if d == DOWN | RIGHT => Some('┌'),
+            d if d == DOWN | LEFT => Some('┐'),
+            d if d == UP | RIGHT => Some('└'),
+            d if d == UP | LEFT => Some('┘'),
+            d if d == UP | DOWN | RIGHT => Some('├'),
+            d if d == UP | DOWN | LEFT => Some('┤'),
+            d if d == DOWN | LEFT | RIGHT => Some('┬'),
+            d if d == UP | LEFT | RIGHT => Some('┴'),
+            d if d == UP | DOWN | LEFT | RIGHT => Some('┼'),
+            d if d & (LEFT | RIGHT) != 0 => Some('─'),
+            _ => Some('│'),
+        })
+        .collect()
+}
+
+/// One character cell of the inline display with its style
+type MmmCell = (char, bool, Colors);
+
+const BLANK_CELL: MmmCell = (
+    ' ',
+    false,
+    Colors {
+        foreground: Some(Color::Reset),
+        background: Some(Color::Reset),
+    },
+);
+
+/// Draws into the rows below the prompt when running inline, where the fullscreen buffer cannot
+/// be used. Only rows that changed since the last flush are written out.
+#[derive(Debug, Default)]
+pub struct MmmInlineCanvas {
+    size: Vec2,
+    /// The terminal row the frame is shown from
+    origin: u16,
+    cursor: Vec2,
+    cells: Vec<MmmCell>,
+    shown: Vec<MmmCell>,
+}
+
+impl MmmCanvas for MmmInlineCanvas {
+    fn clear(&mut self, size: Vec2, origin: u16) {
+        self.size = size;
+        self.origin = origin;
+        self.cursor = Vec2::default();
+        self.cells = vec![BLANK_CELL; size.col as usize * size.row as usize];
+        self.shown.clear();
+    }
+
+    fn move_cursor(&mut self, pos: Vec2) -> MmmResult<&mut Self> {
+        self.cursor = pos;
+        Ok(self)
+    }
+
+    fn styled_print(&mut self, chars: Vec<StyledChar>) -> MmmResult<&mut Self> {
+        for styled_char in chars {
+            if self.cursor.col < self.size.col && self.cursor.row < self.size.row {
+                let idx =
+                    self.cursor.row as usize * self.size.col as usize + self.cursor.col as usize;
+                self.cells[idx] = (styled_char.character, styled_char.bold, styled_char.colour);
+            }
+            self.cursor.col = self.cursor.col.saturating_add(1);
+        }
+        Ok(self)
+    }
+
+    fn draw_boxes(&mut self, _term_size: Vec2, boxes: &[(Vec2, Vec2)]) -> MmmResult<&mut Self> {
+        for (cell, line) in self.cells.iter_mut().zip(get_box_lines(self.size, boxes)) {
+            if let Some(c) = line {
+                *cell = (c, false, BLANK_CELL.2);
+            }
+        }
+        Ok(self)
+    }
+
+    fn flush(&mut self) -> MmmResult<()> {
+        let width = self.size.col.max(1) as usize;
+        let mut stdout = stdout();
+        for (row, cells) in self.cells.chunks(width).enumerate() {
+            if self.shown.chunks(width).nth(row) == Some(cells) {
+                continue;
+            }
+            queue!(stdout, MoveTo(0, self.origin + row as u16))?;
+            let mut style = None;
+            for &(c, bold, colours) in cells {
+                if style != Some((bold, colours)) {
+                    let weight = if bold {
+                        Attribute::Bold
+                    } else {
+                        Attribute::NormalIntensity
+                    };
+                    queue!(stdout, SetAttribute(weight), SetColors(colours))?;
+                    style = Some((bold, colours));
+                }
+                queue!(stdout, Print(c))?;
+            }
+        }
+        queue!(stdout, SetAttribute(Attribute::Reset), ResetColor)?;
+        stdout.flush()?;
+        self.shown = self.cells.clone();
+        Ok(())
+    }
+}
+
+/// A grid of characters standing in for the terminal, styling is dropped so frames can be
+/// compared as plain text
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MmmTextCanvas {
+    size: Vec2,
+    cursor: Vec2,
+    cells: Vec<char>,
+    flushed: String,
+}
+
+#[cfg(test)]
+impl MmmTextCanvas {
+    /// The last flushed frame, one line per row with trailing spaces trimmed
+    pub fn get_frame(&self) -> &str {
+        &self.flushed
+    }
+
+    fn set(&mut self, col: u16, row: u16, c: char) {
+        if col < self.size.col && row < self.size.row {
+            self.cells[row as usize * self.size.col as usize + col as usize] = c;
+        }
+    }
+}
+
+#[cfg(test)]
+impl MmmCanvas for MmmTextCanvas {
+    fn clear(&mut self, size: Vec2, _origin: u16) {
+        self.size = size;
+        self.cursor = Vec2::default();
+        self.cells = vec![' '; size.col as usize * size.row as usize];
+    }
+
+    fn move_cursor(&mut self, pos: Vec2) -> MmmResult<&mut Self> {
+        self.cursor = pos;
+        Ok(self)
+    }
+
+    fn styled_print(&mut self, chars: Vec<StyledChar>) -> MmmResult<&mut Self> {
+        for styled_char in chars {
+            self.set(self.cursor.col, self.cursor.row, styled_char.character);
+            self.cursor.col = self.cursor.col.saturating_add(1);
+        }
+        Ok(self)
+    }
+
+    fn draw_boxes(&mut self, _term_size: Vec2, boxes: &[(Vec2, Vec2)]) -> MmmResult<&mut Self> {
+        for (cell, line) in self.cells.iter_mut().zip(get_box_lines(self.size, boxes)) {
+            if let Some(c) = line {
+                *cell = c;
+            }
+        }
+        Ok(self)
+    }
+
+    fn flush(&mut self) -> MmmResult<()> {
+        self.flushed = self
+            .cells
+            .chunks(self.size.col.max(1) as usize)
+            .map(|row| row.iter().collect::<String>().trim_end().to_string() + "\n")
+            .collect();
+        Ok(())
+    }
+}
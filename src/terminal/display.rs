@@ -0,0 +1,250 @@
+use std::{
+    fs::File,
+    io::{stdout, IsTerminal, Write},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use crossterm::{
+    cursor::{self, Hide, MoveTo, MoveToColumn, MoveUp, Show},
+    event::{DisableMouseCapture, EnableMouseCapture},
+    execute, queue,
+    style::Print,
+    terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType},
+};
+use doubuff::helpers::{start_display, stop_display};
+use terminal_vec2::Vec2;
+
+use crate::error_type::{MmmError, MmmResult};
+
+/// The display while it is up, so stopping it again from an error path or a signal does nothing,
+/// and a panic can stop it where it is now rather than where it started
+static STARTED_DISPLAY: Mutex<Option<MmmDisplay>> = Mutex::new(None);
+
+fn get_started_display() -> MutexGuard<'static, Option<MmmDisplay>> {
+    STARTED_DISPLAY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// How much of the terminal mmm draws in, kept with the layout so headless runs can use either
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MmmDisplay {
+    /// Takes over the whole screen
+    #[default]
+    Fullscreen,
+    /// Draws in `height` rows below the prompt, starting from terminal row `origin`
+    Inline { height: u16, origin: u16 },
+}
+
+impl MmmDisplay {
+    /// An inline display of `height` rows, or the whole screen. The origin is found when the
+    /// display is started.
+    pub fn new(height: Option<u16>) -> Self {
+        match height {
+            Some(height) => MmmDisplay::Inline { height, origin: 0 },
+            None => MmmDisplay::Fullscreen,
+        }
+    }
+
+    /// The terminal row the display starts on, always 0 when fullscreen
+    pub fn get_origin(&self) -> u16 {
+        match self {
+            MmmDisplay::Inline { origin, .. } => *origin,
+            MmmDisplay::Fullscreen => 0,
+        }
+    }
+
+    /// The part of a terminal of `term_size` the display gets to draw in
+    pub fn fit(&self, term_size: Vec2) -> Vec2 {
+        match self {
+            MmmDisplay::Inline { height, .. } => Vec2 {
+                col: term_size.col,
+                row: term_size.row.min(*height),
+            },
+            MmmDisplay::Fullscreen => term_size,
+        }
+    }
+
+    /// Moves an inline display up when the terminal shrinks to `rows`, so its rows stay on screen
+    pub fn resize(&mut self, rows: u16) {
+        if let MmmDisplay::Inline { height, origin } = self {
+            *origin = (*origin).min(rows.saturating_sub(*height));
+        }
+    }
+}
+
+/// Takes over the terminal, capturing the mouse as well as the keyboard. An inline display is
+/// given the row it starts on.
+pub fn start_mmm_display(display: &mut MmmDisplay) -> std::io::Result<()> {
+    *get_started_display() = Some(*display);
+    match display {
+        MmmDisplay::Inline { height, origin } => {
+            enable_raw_mode()?;
+            // Scroll the screen up if there are not enough rows below the prompt, then start
+            // drawing from the prompt row
+            let (_, term_rows) = crossterm::terminal::size()?;
+            let rows = (*height).min(term_rows).max(1);
+            let mut stdout = stdout();
+            queue!(stdout, Hide, EnableMouseCapture, MoveToColumn(0))?;
+            if rows > 1 {
+                queue!(
+                    stdout,
+                    Print("\n".repeat(rows as usize - 1)),
+                    MoveUp(rows - 1)
+                )?;
+            }
+            stdout.flush()?;
+            (_, *origin) = cursor::position()?;
+            track_display(*display);
+            Ok(())
+        }
+        MmmDisplay::Fullscreen => {
+            start_display()?;
+            execute!(stdout(), EnableMouseCapture)
+        }
+    }
+}
+
+/// Records where a display that is up has moved to, for stopping it after a panic
+pub fn track_display(display: MmmDisplay) {
+    let mut started = get_started_display();
+    if started.is_some() {
+        *started = Some(display);
+    }
+}
+
+/// Gives the terminal back in the state it was found, does nothing if it already was
+pub fn stop_mmm_display(display: MmmDisplay) -> std::io::Result<()> {
+    if get_started_display().take().is_none() {
+        return Ok(());
+    }
+    restore_terminal(display)
+}
+
+/// Stops the display wherever it was last tracked to, for when the caller's copy can't be trusted
+pub fn stop_started_display() -> std::io::Result<()> {
+    let Some(display) = get_started_display().take() else {
+        return Ok(());
+    };
+    restore_terminal(display)
+}
+
+fn restore_terminal(display: MmmDisplay) -> std::io::Result<()> {
+    match display {
+        MmmDisplay::Inline { origin, .. } => {
+            // Wipe the rows drawn in and leave the cursor where the prompt was
+            execute!(
+                stdout(),
+                MoveTo(0, origin),
+                Clear(ClearType::FromCursorDown),
+                Show,
+                DisableMouseCapture
+            )?;
+            disable_raw_mode()
+        }
+        MmmDisplay::Fullscreen => {
+            execute!(stdout(), DisableMouseCapture)?;
+            stop_display()
+        }
+    }
+}
+
+/// Points stdout at the terminal when it has been redirected, as it is inside `$(mmm --pick)`, so
+/// the display still reaches the screen. Returns the original stdout for printing results to.
+#[cfg(unix)]
+pub fn take_over_stdout() -> std::io::Result<Option<File>> {
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    if stdout().is_terminal() {
+        return Ok(None);
+    }
+    let tty = File::options().read(true).write(true).open("/dev/tty")?;
+    // SAFETY: dup and dup2 only touch the descriptor table, the duplicate is owned by the
+    // returned file and nothing else holds on to the old stdout descriptor
+    unsafe {
+        let original = libc::dup(libc::STDOUT_FILENO);
+        if original < 0 || libc::dup2(tty.as_raw_fd(), libc::STDOUT_FILENO) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Some(File::from_raw_fd(original)))
+    }
+}
+
+#[cfg(not(unix))]
+pub fn take_over_stdout() -> std::io::Result<Option<File>> {
+    Ok(None)
+}
+
+/// Hands the terminal to another program for the duration of `f`, the caller must redraw the
+/// whole screen afterwards
+pub fn with_display_suspended<T>(display: &mut MmmDisplay, f: impl FnOnce() -> T) -> MmmResult<T> {
+    stop_mmm_display(*display).map_err(|err| MmmError::Terminal(err.to_string()))?;
+    let result = f();
+    start_mmm_display(display).map_err(|err| MmmError::Terminal(err.to_string()))?;
+    Ok(result)
+}
+
+/// Stops mmm the way Ctrl-Z stops other programs, giving the terminal back to the shell, and
+/// returns once it is continued with `fg`. The caller must redraw the whole screen afterwards.
+#[cfg(unix)]
+pub fn suspend_process(display: &mut MmmDisplay) -> MmmResult<()> {
+    // SAFETY: raise only sends a signal to this process, SIGTSTP is left with its default action
+    // of stopping the process until SIGCONT
+    let raised = with_display_suspended(display, || unsafe { libc::raise(libc::SIGTSTP) })?;
+    if raised != 0 {
+        return Err(format!("cannot suspend, {}", std::io::Error::last_os_error()).into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn suspend_process(_display: &mut MmmDisplay) -> MmmResult<()> {
+    Err("suspending is not supported on this platform".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stopping_a_display_that_is_not_up_does_nothing() {
+        // Never started, so this must not touch the terminal however many times it is called,
+        // as the error path, the panic hook and main can all stop the display
+        assert!(get_started_display().is_none());
+        stop_mmm_display(MmmDisplay::Fullscreen).unwrap();
+        stop_mmm_display(MmmDisplay::new(Some(10))).unwrap();
+        stop_started_display().unwrap();
+        // Moving a display that is not up doesn't start it either
+        track_display(MmmDisplay::new(Some(10)));
+        assert!(get_started_display().is_none());
+    }
+
+    #[test]
+    fn inline_display_stays_on_screen_when_the_terminal_shrinks() {
+        let mut display = MmmDisplay::Inline {
+            height: 10,
+            origin: 30,
+        };
+        assert_eq!(
+            display.fit(Vec2 { col: 80, row: 40 }),
+            Vec2 { col: 80, row: 10 }
+        );
+        display.resize(35);
+        assert_eq!(display.get_origin(), 25);
+        display.resize(60);
+        assert_eq!(display.get_origin(), 25);
+        display.resize(6);
+        assert_eq!(display.get_origin(), 0);
+        assert_eq!(
+            display.fit(Vec2 { col: 80, row: 6 }),
+            Vec2 { col: 80, row: 6 }
+        );
+        let mut fullscreen = MmmDisplay::new(None);
+        fullscreen.resize(6);
+        assert_eq!(fullscreen.get_origin(), 0);
+        assert_eq!(
+            fullscreen.fit(Vec2 { col: 80, row: 40 }),
+            Vec2 { col: 80, row: 40 }
+        );
+    }
+}
@@ -1,127 +1,225 @@
-use std::{
-    cmp::{max, min},
-    rc::Rc,
-};
+use std::{collections::BTreeSet, path::PathBuf, rc::Rc};
 
 use crossterm::style::{Color, Colors};
-use doubuff::{
-    boxes::TerminalBoxes,
-    buffer::TerminalBuffer,
-    styled_char::{add_style_to_string, StyledChar},
-};
+use doubuff::styled_char::{add_style_to_string, StyledChar};
 use terminal_vec2::{vec2, Vec2};
 
 use crate::{
     error_type::MmmResult,
-    filesystem::{filter::FilterMatchEnum, MmmDirEntry, MmmFilesys, MmmScoredDirEntry},
+    filesystem::{
+        dir_entry::{format_permissions, format_size, format_time},
+        filter::FilterMatchEnum,
+        MmmDirEntry, MmmFilesys, MmmLinkTarget, MmmMode, MmmScoredDirEntry,
+    },
 };
 
-use super::layout::MmmLayout;
+use super::{
+    canvas::MmmCanvas,
+    layout::{get_parent_top_row, MmmLayout},
+    status::{MmmMessageKind, MmmStatus},
+};
 
+/// Which parts of the screen an update damaged, only those are repainted
 pub struct DrawOps {
     pub background: bool,
+    pub parent: bool,
     pub current_dir: bool,
     pub search_box: bool,
+    pub status: bool,
 }
 
 impl DrawOps {
-    pub fn new(background: bool, current_dir: bool, search_box: bool) -> Self {
+    /// Repaints everything from a blank screen
+    pub fn full() -> Self {
+        DrawOps {
+            background: true,
+            parent: true,
+            current_dir: true,
+            search_box: true,
+            status: true,
+        }
+    }
+
+    /// After moving to another directory
+    pub fn directory() -> Self {
+        DrawOps {
+            background: false,
+            ..Self::full()
+        }
+    }
+
+    /// After the current pane's listing, filter or mode changed
+    pub fn listing() -> Self {
+        DrawOps {
+            parent: false,
+            ..Self::directory()
+        }
+    }
+
+    /// After the selection or marks in the current pane changed
+    pub fn selection() -> Self {
+        DrawOps {
+            search_box: false,
+            ..Self::listing()
+        }
+    }
+
+    /// After a message, the clipboard or a job changed
+    pub fn status_line() -> Self {
         DrawOps {
-            background,
-            current_dir,
-            search_box,
+            status: true,
+            ..Self::nothing()
         }
     }
 
+    pub fn nothing() -> Self {
+        DrawOps {
+            background: false,
+            parent: false,
+            current_dir: false,
+            search_box: false,
+            status: false,
+        }
+    }
+
+    /// Repaints the damaged parts, clearing each first so nothing stale is left behind when a
+    /// list or message gets shorter
     pub fn draw(
         self,
-        term_buffer: &mut TerminalBuffer,
+        term_buffer: &mut impl MmmCanvas,
         filesys: &MmmFilesys,
         layout: &MmmLayout,
+        status: &MmmStatus,
     ) -> MmmResult<()> {
-        if self.is_any() {
-            if self.background {
-                *term_buffer = TerminalBuffer::new(layout.term_size);
-            }
+        if self.background {
+            term_buffer.clear(layout.term_size, layout.display.get_origin());
             draw_background(term_buffer, layout)?;
+            term_buffer
+                .move_cursor(layout.app_pos + vec2!(2, 0)?)?
+                .styled_print(add_style_to_string(
+                    " mmm ",
+                    Color::Reset,
+                    Color::Reset,
+                    false,
+                ))?;
+        }
+        if self.parent {
+            term_buffer.fill(layout.parent_pos, layout.parent_size, ' ')?;
+            // The selected row's marker runs onto the separator, so restore it as well
+            term_buffer.fill(
+                vec2!(layout.vert_sep_pos.col, layout.parent_pos.row)?,
+                vec2!(1, layout.parent_size.row)?,
+                '│',
+            )?;
+            if let Some(pdl) = &filesys.filtered_parent_dir_list {
+                draw_parent_dir(
+                    term_buffer,
+                    pdl,
+                    filesys.parent_current_entry,
+                    filesys.parent_top_row,
+                    layout.parent_pos,
+                    layout.parent_size,
+                    filesys.show_hidden_files,
+                )?;
+            }
+        }
+        if self.current_dir {
+            term_buffer.fill(layout.current_pos, layout.current_size, ' ')?;
             draw_current_dir(
                 term_buffer,
                 &filesys.filtered_current_dir_list,
+                filesys.get_selected_index(),
+                filesys.current_top_row,
+                filesys.get_marked(),
                 layout.current_pos,
                 layout.current_size,
             )?;
+        }
+        if self.search_box {
+            term_buffer.fill(
+                layout.path_disp_pos,
+                vec2!(layout.path_disp_width + layout.search_width, 1)?,
+                ' ',
+            )?;
             draw_search_str(
                 term_buffer,
                 layout.path_disp_pos,
                 layout.path_disp_width,
-                filesys
-                    .get_current_path()
-                    .to_str()
-                    .ok_or("cannot get current path")?,
+                &filesys.get_current_path().to_string_lossy(),
                 layout.search_width,
-                filesys.get_filter(),
+                filesys.get_prompt().unwrap_or(filesys.get_filter()),
             )?;
-            if let Some(pdl) = &filesys.filtered_parent_dir_list {
-                draw_parent_dir(
-                    term_buffer,
-                    pdl,
-                    filesys.parent_current_entry,
-                    layout.parent_pos,
-                    layout.parent_size,
-                    filesys.show_hidden_files,
+        }
+        if self.status {
+            term_buffer.fill(layout.jobs_pos, vec2!(layout.jobs_width, 1)?, '─')?;
+            if layout.status_width == 0 {
+                // Without a status line the separator between the panes meets the bottom border
+                term_buffer.fill(
+                    vec2!(layout.vert_sep_pos.col, layout.jobs_pos.row)?,
+                    vec2!(1, 1)?,
+                    '┴',
                 )?;
             }
-            term_buffer
-                .move_cursor(layout.app_pos + vec2!(2, 0)?)?
-                .styled_print(add_style_to_string(
-                    " mmm ",
-                    Color::Reset,
-                    Color::Reset,
-                    false,
-                ))?;
+            draw_jobs(term_buffer, filesys, layout.jobs_pos, layout.jobs_width)?;
+            term_buffer.fill(layout.status_pos, vec2!(layout.status_width, 1)?, ' ')?;
+            draw_status(
+                term_buffer,
+                filesys,
+                status,
+                layout.status_pos,
+                layout.status_width,
+            )?;
         }
         Ok(())
     }
 
-    fn is_any(&self) -> bool {
-        self.background || self.current_dir || self.search_box
+    pub fn is_any(&self) -> bool {
+        self.background || self.parent || self.current_dir || self.search_box || self.status
     }
 }
 
-pub fn draw_background(term_buffer: &mut TerminalBuffer, layout: &MmmLayout) -> MmmResult<()> {
-    let term_size = crossterm::terminal::size()?.into();
-    let mut boxes = TerminalBoxes::new_thin(term_size);
-    boxes
-        .add_box(layout.app_pos, layout.app_size)
-        .add_box(layout.vert_sep_pos, layout.vert_sep_size)
-        .add_box(layout.horiz_sep_pos, layout.horiz_sep_size);
-    term_buffer.draw_box(boxes)?;
+pub fn draw_background(term_buffer: &mut impl MmmCanvas, layout: &MmmLayout) -> MmmResult<()> {
+    let mut boxes = vec![
+        (layout.app_pos, layout.app_size),
+        (layout.vert_sep_pos, layout.vert_sep_size),
+        (layout.horiz_sep_pos, layout.horiz_sep_size),
+    ];
+    if layout.status_width > 0 {
+        boxes.push((layout.status_sep_pos, layout.horiz_sep_size));
+    }
+    term_buffer.draw_boxes(layout.term_size, &boxes)?;
     Ok(())
 }
 
 pub fn draw_current_dir(
-    term_buffer: &mut TerminalBuffer,
+    term_buffer: &mut impl MmmCanvas,
     filtered_list: &[MmmScoredDirEntry],
+    selected: usize,
+    top_row: usize,
+    marked: &BTreeSet<PathBuf>,
     pos: Vec2,
     size: Vec2,
 ) -> MmmResult<()> {
     for i in 0..size.row as usize {
-        if let Some(entry) = filtered_list.get(i) {
+        if let Some(entry) = filtered_list.get(i + top_row) {
             let fg_colour;
-            let bg_colour;
+            let mut bg_colour;
             let bold;
             match entry.entry.as_ref() {
-                MmmDirEntry::Directory { name: _, path: _ } => {
-                    fg_colour = Color::Blue;
+                dir_entry if marked.contains(dir_entry.get_path()) => {
+                    fg_colour = Color::Yellow;
                     bg_colour = Color::Reset;
-                    bold = false;
+                    bold = true;
                 }
-                _ => {
-                    fg_colour = Color::White;
+                dir_entry => {
+                    fg_colour = get_entry_colour(dir_entry);
                     bg_colour = Color::Reset;
                     bold = false;
                 }
             };
+            if i + top_row == selected {
+                bg_colour = Color::DarkGrey;
+            }
             let formatted_name = clamp_string(entry.entry.get_name(), size.col as usize);
             let mut styled_str = add_style_to_string(formatted_name, fg_colour, bg_colour, bold);
             for (i, c) in styled_str.iter_mut().enumerate() {
@@ -131,7 +229,7 @@ pub fn draw_current_dir(
                     *c = StyledChar {
                         character: c.character,
                         bold: true,
-                        colour: Colors::new(Color::Red, Color::Reset),
+                        colour: Colors::new(Color::Red, bg_colour),
                     }
                 }
             }
@@ -139,6 +237,29 @@ pub fn draw_current_dir(
             term_buffer
                 .move_cursor(print_pos)?
                 .styled_print(styled_str)?;
+            if let MmmDirEntry::Link {
+                linked_path: Some(linked_path),
+                ..
+            } = entry.entry.as_ref()
+            {
+                let remaining_width = size.col as usize - formatted_name.chars().count();
+                let link_str = format!(" -> {}", linked_path.display());
+                let formatted_link = clamp_string(&link_str, remaining_width);
+                // The target is dimmed, but not so far that it vanishes into the selection
+                let link_colour = if i + top_row == selected {
+                    Color::Grey
+                } else {
+                    Color::DarkGrey
+                };
+                if !formatted_link.is_empty() {
+                    term_buffer.styled_print(add_style_to_string(
+                        formatted_link,
+                        link_colour,
+                        bg_colour,
+                        false,
+                    ))?;
+                }
+            }
         } else {
             break;
         }
@@ -146,25 +267,29 @@ pub fn draw_current_dir(
     Ok(())
 }
 
+/// Directories and links to them are blue and cyan, broken links red, everything else white
+fn get_entry_colour(entry: &MmmDirEntry) -> Color {
+    match entry {
+        MmmDirEntry::Directory { .. } => Color::Blue,
+        MmmDirEntry::Link { target, .. } => match target {
+            MmmLinkTarget::Directory => Color::Cyan,
+            MmmLinkTarget::File => Color::White,
+            MmmLinkTarget::Broken => Color::Red,
+        },
+        _ => Color::White,
+    }
+}
+
 pub fn draw_parent_dir(
-    term_buffer: &mut TerminalBuffer,
+    term_buffer: &mut impl MmmCanvas,
     dir_list: &[Rc<MmmDirEntry>],
     selected: usize,
+    scrolled: Option<usize>,
     pos: Vec2,
     size: Vec2,
     allow_hidden: bool,
 ) -> MmmResult<()> {
-    let len = dir_list.len();
-    // Calculate the top row using clamped centering formula
-    let top_row: usize = max(
-        min(
-            selected as i32 - ((size.row as i32 - 1) / 2),
-            (len as i32) - (1 + (size.row as i32)),
-        ),
-        0,
-    )
-    .try_into()
-    .expect("unable to convert top_row to usize");
+    let top_row = get_parent_top_row(dir_list.len(), selected, size.row, scrolled);
     for i in 0..size.row as usize {
         if let Some(entry) = dir_list.get(i + top_row) {
             let is_selected = i + top_row == selected;
@@ -187,18 +312,9 @@ pub fn draw_parent_dir(
                 bg_colour = Color::Reset;
                 bold = true;
             } else {
-                match entry.as_ref() {
-                    MmmDirEntry::Directory { name: _, path: _ } => {
-                        fg_colour = Color::Blue;
-                        bg_colour = Color::Reset;
-                        bold = false;
-                    }
-                    _ => {
-                        fg_colour = Color::White;
-                        bg_colour = Color::Reset;
-                        bold = false;
-                    }
-                };
+                fg_colour = get_entry_colour(entry);
+                bg_colour = Color::Reset;
+                bold = false;
             }
             let formatted_name = clamp_string(entry.get_name(), size.col as usize);
             let styled_str = add_style_to_string(formatted_name, fg_colour, bg_colour, bold);
@@ -223,7 +339,7 @@ pub fn draw_parent_dir(
 }
 
 pub fn draw_search_str(
-    term_buffer: &mut TerminalBuffer,
+    term_buffer: &mut impl MmmCanvas,
     position: Vec2,
     path_width: usize,
     path_str: &str,
@@ -266,9 +382,303 @@ pub fn draw_search_str(
     Ok(())
 }
 
+/// Draws the status line, the selected entry's details or a message on the left and the listing
+/// state on the right
+pub fn draw_status(
+    term_buffer: &mut impl MmmCanvas,
+    filesys: &MmmFilesys,
+    status: &MmmStatus,
+    pos: Vec2,
+    width: usize,
+) -> MmmResult<()> {
+    if width == 0 {
+        return Ok(());
+    }
+    let mut right_str = String::new();
+    if let Some(mode_name) = filesys.get_mode().get_name() {
+        right_str += &format!("{} ", mode_name);
+    } else if !filesys.filter_is_empty() {
+        right_str += "fuzzy ";
+    }
+    if filesys.show_hidden_files {
+        right_str += "hidden ";
+    }
+    right_str += &format!(
+        "{}/{}",
+        filesys.filtered_current_dir_list.len(),
+        filesys.get_entry_count()
+    );
+    let right_str = clamp_string(&right_str, width);
+    let left_width = width.saturating_sub(right_str.chars().count() + 1);
+    let (left_str, left_colour) = match status.get_message() {
+        Some(message) if message.kind == MmmMessageKind::Error => {
+            (message.text.clone(), Color::Red)
+        }
+        Some(message) => (message.text.clone(), Color::Yellow),
+        // Commands aren't files, so show what the selected one will run instead of file details
+        None if filesys.get_mode() == MmmMode::Commands => (
+            filesys
+                .get_selected_command()
+                .map(|(_, command)| command.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            Color::DarkGrey,
+        ),
+        None => (
+            filesys
+                .get_selected_entry()
+                .map(|entry| format_entry_details(&entry))
+                .unwrap_or_default(),
+            Color::DarkGrey,
+        ),
+    };
+    term_buffer
+        .move_cursor(pos)?
+        .styled_print(add_style_to_string(
+            clamp_string(&left_str, left_width),
+            left_colour,
+            Color::Reset,
+            false,
+        ))?
+        .move_cursor(pos + vec2!(width - right_str.chars().count(), 0)?)?
+        .styled_print(add_style_to_string(
+            right_str,
+            Color::DarkGrey,
+            Color::Reset,
+            false,
+        ))?;
+    Ok(())
+}
+
+fn format_entry_details(entry: &MmmDirEntry) -> String {
+    let mut details = match std::fs::symlink_metadata(entry.get_path()) {
+        Ok(metadata) => {
+            let mut details = format_permissions(&metadata);
+            if !metadata.is_dir() {
+                details += &format!(" {}", format_size(metadata.len()));
+            }
+            if let Ok(modified) = metadata.modified() {
+                details += &format!(" {} UTC", format_time(modified, ' '));
+            }
+            details
+        }
+        Err(err) => err.to_string(),
+    };
+    if let MmmDirEntry::Link { linked_path, .. } = entry {
+        match linked_path {
+            Some(linked_path) => details += &format!(" -> {}", linked_path.display()),
+            None => details += " -> ?",
+        }
+        if entry.is_broken_link() {
+            details += " (broken)";
+        }
+    }
+    details
+}
+
+/// Draws the running job's progress, or the pending clipboard, into the bottom border
+pub fn draw_jobs(
+    term_buffer: &mut impl MmmCanvas,
+    filesys: &MmmFilesys,
+    pos: Vec2,
+    width: usize,
+) -> MmmResult<()> {
+    if let Some(status) = filesys.get_job_status() {
+        let label = format!(" {} ", status.label);
+        let label = clamp_string(&label, width);
+        let bar_width = width - label.chars().count();
+        let filled = (status.fraction * bar_width as f64).round() as usize;
+        term_buffer
+            .move_cursor(pos)?
+            .styled_print(add_style_to_string(
+                label,
+                Color::Reset,
+                Color::Reset,
+                false,
+            ))?
+            .styled_print(add_style_to_string(
+                &"━".repeat(filled),
+                Color::Green,
+                Color::Reset,
+                false,
+            ))?;
+    } else if let Some(clipboard) = filesys.get_clipboard() {
+        let label = format!(
+            " {} {}, conflicts {} ",
+            clipboard.paths.len(),
+            if clipboard.cut { "cut" } else { "yanked" },
+            filesys.get_conflict_policy().get_name()
+        );
+        term_buffer
+            .move_cursor(pos)?
+            .styled_print(add_style_to_string(
+                clamp_string(&label, width),
+                Color::DarkGrey,
+                Color::Reset,
+                false,
+            ))?;
+    }
+    Ok(())
+}
+
 fn clamp_string(input: &str, max_len: usize) -> &str {
     input
         .char_indices()
         .nth(max_len)
         .map_or(input, |(idx, _)| &input[..idx])
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        fs::{self, File, Permissions},
+        os::unix::fs::PermissionsExt,
+        path::{Path, PathBuf},
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+    use terminal_vec2::vec2;
+
+    use crate::test_util::{key, make_tree, run_script, type_str, MmmTestDir};
+
+    /// Creates a fixture tree under `mmm-snapshots` in the test directory, with fixed
+    /// permissions and modification times so the status line doesn't depend on the umask or when
+    /// the test ran. The frames only show the end of the path, so where the test directory is
+    /// doesn't matter.
+    fn make_fixture(name: &str, dirs: &[&str], files: &[&str]) -> MmmTestDir {
+        let root = make_tree(&format!("mmm-snapshots/{}", name), dirs, files);
+        let modified = UNIX_EPOCH + Duration::from_secs(1704067200);
+        let mut paths = vec![root.clone()];
+        while let Some(path) = paths.pop() {
+            let mode = if path.is_dir() {
+                paths.extend(fs::read_dir(&path).unwrap().map(|e| e.unwrap().path()));
+                0o755
+            } else {
+                0o644
+            };
+            fs::set_permissions(&path, Permissions::from_mode(mode)).unwrap();
+            File::open(&path).unwrap().set_modified(modified).unwrap();
+        }
+        root
+    }
+
+    fn make_project(name: &str) -> MmmTestDir {
+        make_fixture(
+            name,
+            &["project/src/bin", "project/target", "project/.git"],
+            &[
+                "project/Cargo.toml",
+                "project/README.md",
+                "project/src/main.rs",
+                "project/src/lib.rs",
+                "project/src/a_source_file_with_a_very_long_name_indeed.rs",
+            ],
+        )
+    }
+
+    /// Compares a frame to its golden file in `src/terminal/snapshots`, set
+    /// `MMM_UPDATE_SNAPSHOTS` to write the golden files instead
+    fn assert_snapshot(name: &str, frame: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/terminal/snapshots")
+            .join(format!("{}.txt", name));
+        if std::env::var_os("MMM_UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, frame).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|_| panic!("missing snapshot {}", path.display()));
+        assert!(
+            frame == expected,
+            "frame differs from {}, rerun with MMM_UPDATE_SNAPSHOTS=1 if this is intended\n\
+             expected:\n{}\nfound:\n{}",
+            path.display(),
+            expected,
+            frame
+        );
+    }
+
+    async fn snapshot(name: &str, path: PathBuf, col: u16, row: u16, events: Vec<Event>) {
+        let (_, canvas) = run_script(path, vec2!(col, row).unwrap(), events)
+            .await
+            .unwrap();
+        assert_snapshot(name, canvas.get_frame());
+    }
+
+    #[tokio::test]
+    async fn project_source() {
+        let root = make_project("project_source");
+        snapshot("project_source", root.join("project/src"), 60, 20, vec![]).await;
+    }
+
+    #[tokio::test]
+    async fn project_filtered() {
+        let root = make_project("project_filtered");
+        let events = type_str("rs");
+        snapshot("project_filtered", root.join("project/src"), 60, 20, events).await;
+    }
+
+    #[tokio::test]
+    async fn project_narrow() {
+        // Long names are clamped to the panes and the path loses its start
+        let root = make_project("project_narrow");
+        snapshot("project_narrow", root.join("project/src"), 36, 12, vec![]).await;
+    }
+
+    #[tokio::test]
+    async fn project_without_status() {
+        let root = make_project("project_without_status");
+        snapshot(
+            "project_without_status",
+            root.join("project"),
+            40,
+            7,
+            vec![],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn minimum_size() {
+        let root = make_project("minimum_size");
+        snapshot("minimum_size", root.join("project"), 10, 4, vec![]).await;
+    }
+
+    #[tokio::test]
+    async fn parent_centred() {
+        let dirs: Vec<String> = (0..30).map(|i| format!("many/dir{:02}", i)).collect();
+        let dirs: Vec<&str> = dirs.iter().map(String::as_str).collect();
+        let root = make_fixture("parent_centred", &dirs, &[]);
+        snapshot("parent_centred", root.join("many/dir15"), 50, 14, vec![]).await;
+    }
+
+    #[tokio::test]
+    async fn parent_near_end() {
+        let dirs: Vec<String> = (0..30).map(|i| format!("many/dir{:02}", i)).collect();
+        let dirs: Vec<&str> = dirs.iter().map(String::as_str).collect();
+        let root = make_fixture("parent_near_end", &dirs, &[]);
+        snapshot("parent_near_end", root.join("many/dir28"), 50, 14, vec![]).await;
+    }
+
+    #[tokio::test]
+    async fn parent_after_sibling_moves() {
+        // Only the damaged parts are repainted, so the old marker has to be cleared
+        let dirs: Vec<String> = (0..30).map(|i| format!("many/dir{:02}", i)).collect();
+        let dirs: Vec<&str> = dirs.iter().map(String::as_str).collect();
+        let root = make_fixture("parent_after_sibling_moves", &dirs, &[]);
+        let alt_down = Event::Key(KeyEvent::new(KeyCode::Down, KeyModifiers::ALT));
+        let events = vec![alt_down.clone(), alt_down];
+        let path = root.join("many/dir15");
+        snapshot("parent_after_sibling_moves", path, 50, 14, events).await;
+    }
+
+    #[tokio::test]
+    async fn current_scrolled() {
+        let files: Vec<String> = (0..30).map(|i| format!("list/file{:02}", i)).collect();
+        let files: Vec<&str> = files.iter().map(String::as_str).collect();
+        let root = make_fixture("current_scrolled", &["list"], &files);
+        let events = vec![key(KeyCode::Down); 20];
+        snapshot("current_scrolled", root.join("list"), 50, 14, events).await;
+    }
+}
@@ -1,9 +1,30 @@
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
 use terminal_vec2::{vec2, Vec2};
 
-use crate::{error_type::MmmResult, filesystem::MmmFilesys};
+use crate::{
+    error_type::MmmResult,
+    filesystem::{
+        bulk_rename::edit_names_in_editor,
+        shell::{run_shell_command, run_subshell},
+        MmmFilesys, MmmMode,
+    },
+};
+
+use super::{
+    display::{suspend_process, track_display, with_display_suspended, MmmDisplay},
+    draw::DrawOps,
+    layout::{get_parent_top_row, get_terminal_size, MmmLayout},
+    status::MmmStatus,
+};
 
-use super::{draw::DrawOps, layout::MmmLayout};
+/// How close together two clicks on the same cell must be to count as a double click
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+/// How many rows a pane moves for each notch of the scroll wheel
+const SCROLL_ROWS: isize = 3;
 
 pub enum MmmEventType {
     Key(char),
@@ -11,10 +32,37 @@ pub enum MmmEventType {
     Enter,
     NextEntry,
     PrevEntry,
+    Tab,
     Backspace,
     Escape,
     Resize(u16, u16),
     ToggleHidden,
+    MakeDir,
+    Trash,
+    Yank,
+    Cut,
+    Paste,
+    Undo,
+    Redo,
+    Mark,
+    BulkRename,
+    CancelJobs,
+    CyclePolicy,
+    AltKey(char),
+    AwaitBookmarkKey,
+    OpenBookmarks,
+    OpenJump,
+    OpenCommands,
+    Subshell,
+    Suspend,
+    HistoryBack,
+    HistoryForward,
+    NextSibling,
+    PrevSibling,
+    Click(u16, u16),
+    DoubleClick(u16, u16),
+    ScrollUp(u16, u16),
+    ScrollDown(u16, u16),
 }
 
 pub enum MmmStateUpdateType {
@@ -26,9 +74,62 @@ pub enum MmmStateUpdateType {
     ClearSearch,
     Resize(u16, u16),
     ToggleHidden,
+    MakeDir,
+    Trash,
+    Yank,
+    Cut,
+    Paste,
+    Undo,
+    Redo,
+    Mark,
+    BulkRename,
+    CancelJobs,
+    CyclePolicy,
+    Tick,
+    AwaitBookmarkKey,
+    SetBookmark(char),
+    JumpToBookmark(char),
+    OpenBookmarks,
+    DeleteBookmark,
+    RenameBookmark,
+    FinishBookmarkRename,
+    CancelPrompt,
+    AddPromptChar(char),
+    DeletePromptChar,
+    CloseMode,
+    OpenJump,
+    OpenCommands,
+    RunCommand,
+    Subshell,
+    Suspend,
+    HistoryBack,
+    HistoryForward,
+    OpenGoTo(char),
+    DeleteChar,
+    CompletePath,
+    GoToPath,
+    NextSibling,
+    PrevSibling,
+    Click(u16, u16),
+    DoubleClick(u16, u16),
+    Scroll(u16, u16, isize),
+    Pick,
     Exit,
 }
 
+impl MmmStateUpdateType {
+    /// Whether processing this update hands the terminal over to another program
+    pub fn suspends_display(&self) -> bool {
+        matches!(
+            self,
+            MmmStateUpdateType::BulkRename
+                | MmmStateUpdateType::RunCommand
+                | MmmStateUpdateType::Subshell
+                | MmmStateUpdateType::Suspend
+        )
+    }
+}
+
 impl MmmLayout {
     pub fn process_resize_event(&mut self, new_size: Vec2) -> MmmResult<()> {
         #[cfg(not(target_os = "windows"))]
@@ -38,15 +139,50 @@ impl MmmLayout {
             col: new_size.col + 1,
             row: new_size.row + 1,
         };
-        *self = Self::from_size(new_terminal_size)?;
+        // An inline display only gets some of the rows, and has to move up if the terminal
+        // shrinks below it
+        let mut display = self.display;
+        display.resize(new_terminal_size.row);
+        *self = Self::from_size(display.fit(new_terminal_size))?;
+        self.display = display;
+        track_display(display);
         Ok(())
     }
 }
 
-pub fn decode_crossterm_event(event: Option<Event>) -> Option<MmmEventType> {
+/// Remembers the last click so a second one on the same cell soon after is a double click
+#[derive(Debug, Default)]
+pub struct MmmClickTracker {
+    last_click: Option<(Instant, u16, u16)>,
+}
+
+impl MmmClickTracker {
+    fn is_double_click(&mut self, col: u16, row: u16) -> bool {
+        let now = Instant::now();
+        let double_click = matches!(
+            self.last_click,
+            Some((time, last_col, last_row))
+                if last_col == col && last_row == row && now - time < DOUBLE_CLICK_TIME
+        );
+        // A third click starts a new pair rather than being another double click
+        self.last_click = if double_click {
+            None
+        } else {
+            Some((now, col, row))
+        };
+        double_click
+    }
+}
+
+pub fn decode_crossterm_event(
+    event: Option<Event>,
+    display: MmmDisplay,
+    clicks: &mut MmmClickTracker,
+) -> Option<MmmEventType> {
     if let Some(event) = event {
         match event {
             Event::Key(c) => decode_key_event(c),
+            Event::Mouse(m) => decode_mouse_event(m, display, clicks),
             Event::Resize(col, row) => Some(MmmEventType::Resize(col, row)),
             _ => None,
         }
@@ -55,39 +191,246 @@ pub fn decode_crossterm_event(event: Option<Event>) -> Option<MmmEventType> {
     }
 }
 
+fn decode_mouse_event(
+    mouse_event: MouseEvent,
+    display: MmmDisplay,
+    clicks: &mut MmmClickTracker,
+) -> Option<MmmEventType> {
+    // Rows above an inline display belong to whatever was on screen before
+    let (col, row) = (
+        mouse_event.column,
+        mouse_event.row.checked_sub(display.get_origin())?,
+    );
+    match mouse_event.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            if clicks.is_double_click(col, row) {
+                Some(MmmEventType::DoubleClick(col, row))
+            } else {
+                Some(MmmEventType::Click(col, row))
+            }
+        }
+        MouseEventKind::ScrollUp => Some(MmmEventType::ScrollUp(col, row)),
+        MouseEventKind::ScrollDown => Some(MmmEventType::ScrollDown(col, row)),
+        _ => None,
+    }
+}
+
 fn decode_key_event(key_event: KeyEvent) -> Option<MmmEventType> {
     match key_event.code {
         KeyCode::Char(c) => {
             if c == ' ' {
                 Some(MmmEventType::Space)
-            } else if c == 'h' && key_event.modifiers.contains(KeyModifiers::CONTROL) {
-                Some(MmmEventType::ToggleHidden)
+            } else if key_event.modifiers.contains(KeyModifiers::CONTROL) {
+                decode_control_key(c)
+            } else if key_event.modifiers.contains(KeyModifiers::ALT) {
+                Some(MmmEventType::AltKey(c))
             } else {
                 Some(MmmEventType::Key(c))
             }
         }
         KeyCode::Enter => Some(MmmEventType::Enter),
-        KeyCode::BackTab => Some(MmmEventType::PrevEntry),
-        KeyCode::Tab => Some(MmmEventType::NextEntry),
+        KeyCode::Down if key_event.modifiers.contains(KeyModifiers::ALT) => {
+            Some(MmmEventType::NextSibling)
+        }
+        KeyCode::Up if key_event.modifiers.contains(KeyModifiers::ALT) => {
+            Some(MmmEventType::PrevSibling)
+        }
+        KeyCode::BackTab | KeyCode::Up => Some(MmmEventType::PrevEntry),
+        KeyCode::Tab => Some(MmmEventType::Tab),
+        KeyCode::Down => Some(MmmEventType::NextEntry),
+        KeyCode::Left if key_event.modifiers.contains(KeyModifiers::ALT) => {
+            Some(MmmEventType::HistoryBack)
+        }
+        KeyCode::Right if key_event.modifiers.contains(KeyModifiers::ALT) => {
+            Some(MmmEventType::HistoryForward)
+        }
         KeyCode::Backspace => Some(MmmEventType::Backspace),
         KeyCode::Esc => Some(MmmEventType::Escape),
         _ => None,
     }
 }
 
+fn decode_control_key(c: char) -> Option<MmmEventType> {
+    match c {
+        'h' => Some(MmmEventType::ToggleHidden),
+        'n' => Some(MmmEventType::MakeDir),
+        'd' => Some(MmmEventType::Trash),
+        'y' => Some(MmmEventType::Yank),
+        'x' => Some(MmmEventType::Cut),
+        'p' => Some(MmmEventType::Paste),
+        'u' => Some(MmmEventType::Undo),
+        'r' => Some(MmmEventType::Redo),
+        't' => Some(MmmEventType::Mark),
+        'e' => Some(MmmEventType::BulkRename),
+        'c' => Some(MmmEventType::CancelJobs),
+        'o' => Some(MmmEventType::CyclePolicy),
+        'b' => Some(MmmEventType::AwaitBookmarkKey),
+        'g' => Some(MmmEventType::OpenBookmarks),
+        'f' => Some(MmmEventType::OpenJump),
+        'k' => Some(MmmEventType::OpenCommands),
+        's' => Some(MmmEventType::Subshell),
+        'z' => Some(MmmEventType::Suspend),
+        _ => None,
+    }
+}
+
 pub fn get_state_update_type(
     event: MmmEventType,
     filesys_state: &MmmFilesys,
+) -> Option<MmmStateUpdateType> {
+    match filesys_state.get_mode() {
+        MmmMode::Browse => get_browse_update_type(event, filesys_state),
+        MmmMode::AwaitBookmarkKey => match event {
+            MmmEventType::Key(c) => Some(MmmStateUpdateType::SetBookmark(c)),
+            MmmEventType::Resize(col, row) => Some(MmmStateUpdateType::Resize(col, row)),
+            MmmEventType::Suspend => Some(MmmStateUpdateType::Suspend),
+            _ => Some(MmmStateUpdateType::CloseMode),
+        },
+        MmmMode::Jump => match event {
+            MmmEventType::Escape | MmmEventType::OpenJump => Some(MmmStateUpdateType::CloseMode),
+            MmmEventType::Backspace if filesys_state.filter_is_empty() => {
+                Some(MmmStateUpdateType::CloseMode)
+            }
+            MmmEventType::Enter => Some(MmmStateUpdateType::NavInto),
+            MmmEventType::Key(_)
+            | MmmEventType::Space
+            | MmmEventType::NextEntry
+            | MmmEventType::PrevEntry
+            | MmmEventType::Tab
+            | MmmEventType::Backspace
+            | MmmEventType::Resize(_, _)
+            | MmmEventType::Suspend
+            | MmmEventType::Click(_, _)
+            | MmmEventType::DoubleClick(_, _)
+            | MmmEventType::ScrollUp(_, _)
+            | MmmEventType::ScrollDown(_, _) => get_browse_update_type(event, filesys_state),
+            _ => None,
+        },
+        MmmMode::Bookmarks => match event {
+            MmmEventType::Escape | MmmEventType::OpenBookmarks => {
+                Some(MmmStateUpdateType::CloseMode)
+            }
+            MmmEventType::Backspace if filesys_state.filter_is_empty() => {
+                Some(MmmStateUpdateType::CloseMode)
+            }
+            MmmEventType::Enter => Some(MmmStateUpdateType::NavInto),
+            MmmEventType::Trash => Some(MmmStateUpdateType::DeleteBookmark),
+            MmmEventType::MakeDir => Some(MmmStateUpdateType::RenameBookmark),
+            MmmEventType::Key(_)
+            | MmmEventType::Space
+            | MmmEventType::NextEntry
+            | MmmEventType::PrevEntry
+            | MmmEventType::Tab
+            | MmmEventType::Backspace
+            | MmmEventType::Resize(_, _)
+            | MmmEventType::Suspend
+            | MmmEventType::Click(_, _)
+            | MmmEventType::DoubleClick(_, _)
+            | MmmEventType::ScrollUp(_, _)
+            | MmmEventType::ScrollDown(_, _) => get_browse_update_type(event, filesys_state),
+            _ => None,
+        },
+        MmmMode::RenameBookmark(_) => match event {
+            MmmEventType::Escape => Some(MmmStateUpdateType::CancelPrompt),
+            MmmEventType::Enter => Some(MmmStateUpdateType::FinishBookmarkRename),
+            MmmEventType::Backspace => Some(MmmStateUpdateType::DeletePromptChar),
+            MmmEventType::Space => Some(MmmStateUpdateType::AddPromptChar(' ')),
+            MmmEventType::Key(c) => Some(MmmStateUpdateType::AddPromptChar(c)),
+            MmmEventType::Resize(_, _) | MmmEventType::Suspend => {
+                get_browse_update_type(event, filesys_state)
+            }
+            _ => None,
+        },
+        MmmMode::Commands => match event {
+            MmmEventType::Escape | MmmEventType::OpenCommands => {
+                Some(MmmStateUpdateType::CloseMode)
+            }
+            MmmEventType::Backspace if filesys_state.filter_is_empty() => {
+                Some(MmmStateUpdateType::CloseMode)
+            }
+            MmmEventType::Enter | MmmEventType::Space => Some(MmmStateUpdateType::RunCommand),
+            MmmEventType::Key(_)
+            | MmmEventType::NextEntry
+            | MmmEventType::PrevEntry
+            | MmmEventType::Tab
+            | MmmEventType::Backspace
+            | MmmEventType::Resize(_, _)
+            | MmmEventType::Suspend
+            | MmmEventType::Click(_, _)
+            | MmmEventType::ScrollUp(_, _)
+            | MmmEventType::ScrollDown(_, _) => get_browse_update_type(event, filesys_state),
+            _ => None,
+        },
+        MmmMode::GoTo => match event {
+            MmmEventType::Escape => Some(MmmStateUpdateType::CloseMode),
+            MmmEventType::Backspace => Some(MmmStateUpdateType::DeleteChar),
+            MmmEventType::Enter => Some(MmmStateUpdateType::GoToPath),
+            MmmEventType::Tab => Some(MmmStateUpdateType::CompletePath),
+            MmmEventType::Space => Some(MmmStateUpdateType::AddChar(' ')),
+            MmmEventType::Key(c) => Some(MmmStateUpdateType::AddChar(c)),
+            MmmEventType::NextEntry
+            | MmmEventType::PrevEntry
+            | MmmEventType::Resize(_, _)
+            | MmmEventType::Suspend
+            | MmmEventType::Click(_, _)
+            | MmmEventType::DoubleClick(_, _)
+            | MmmEventType::ScrollUp(_, _)
+            | MmmEventType::ScrollDown(_, _) => get_browse_update_type(event, filesys_state),
+            _ => None,
+        },
+    }
+}
+
+fn get_browse_update_type(
+    event: MmmEventType,
+    filesys_state: &MmmFilesys,
 ) -> Option<MmmStateUpdateType> {
     match event {
-        MmmEventType::Enter => None, // Disabled for now
+        MmmEventType::Enter if filesys_state.picker.is_some() => Some(MmmStateUpdateType::Pick),
+        MmmEventType::Enter => None,
+        // A path typed from the root or home replaces the filter with a go to prompt
+        MmmEventType::Key(c @ ('/' | '~'))
+            if filesys_state.filter_is_empty() && filesys_state.get_mode() == MmmMode::Browse =>
+        {
+            Some(MmmStateUpdateType::OpenGoTo(c))
+        }
         MmmEventType::Key(c) => Some(MmmStateUpdateType::AddChar(c)),
         MmmEventType::Escape => Some(MmmStateUpdateType::Exit),
-        MmmEventType::NextEntry => Some(MmmStateUpdateType::NextEntry),
+        MmmEventType::NextEntry | MmmEventType::Tab => Some(MmmStateUpdateType::NextEntry),
         MmmEventType::PrevEntry => Some(MmmStateUpdateType::PrevEntry),
         MmmEventType::Resize(col, row) => Some(MmmStateUpdateType::Resize(col, row)),
         MmmEventType::Space => Some(MmmStateUpdateType::NavInto),
         MmmEventType::ToggleHidden => Some(MmmStateUpdateType::ToggleHidden),
+        MmmEventType::MakeDir => Some(MmmStateUpdateType::MakeDir),
+        MmmEventType::Trash => Some(MmmStateUpdateType::Trash),
+        MmmEventType::Yank => Some(MmmStateUpdateType::Yank),
+        MmmEventType::Cut => Some(MmmStateUpdateType::Cut),
+        MmmEventType::Paste => Some(MmmStateUpdateType::Paste),
+        MmmEventType::Undo => Some(MmmStateUpdateType::Undo),
+        MmmEventType::Redo => Some(MmmStateUpdateType::Redo),
+        MmmEventType::Mark => Some(MmmStateUpdateType::Mark),
+        MmmEventType::BulkRename => Some(MmmStateUpdateType::BulkRename),
+        MmmEventType::CancelJobs => Some(MmmStateUpdateType::CancelJobs),
+        MmmEventType::CyclePolicy => Some(MmmStateUpdateType::CyclePolicy),
+        MmmEventType::AltKey(c) => Some(MmmStateUpdateType::JumpToBookmark(c)),
+        MmmEventType::AwaitBookmarkKey => Some(MmmStateUpdateType::AwaitBookmarkKey),
+        MmmEventType::OpenBookmarks => Some(MmmStateUpdateType::OpenBookmarks),
+        MmmEventType::OpenJump => Some(MmmStateUpdateType::OpenJump),
+        MmmEventType::OpenCommands => Some(MmmStateUpdateType::OpenCommands),
+        MmmEventType::Subshell => Some(MmmStateUpdateType::Subshell),
+        MmmEventType::Suspend => Some(MmmStateUpdateType::Suspend),
+        MmmEventType::HistoryBack => Some(MmmStateUpdateType::HistoryBack),
+        MmmEventType::HistoryForward => Some(MmmStateUpdateType::HistoryForward),
+        MmmEventType::NextSibling => Some(MmmStateUpdateType::NextSibling),
+        MmmEventType::PrevSibling => Some(MmmStateUpdateType::PrevSibling),
+        MmmEventType::Click(col, row) => Some(MmmStateUpdateType::Click(col, row)),
+        MmmEventType::DoubleClick(col, row) => Some(MmmStateUpdateType::DoubleClick(col, row)),
+        MmmEventType::ScrollUp(col, row) => {
+            Some(MmmStateUpdateType::Scroll(col, row, -SCROLL_ROWS))
+        }
+        MmmEventType::ScrollDown(col, row) => {
+            Some(MmmStateUpdateType::Scroll(col, row, SCROLL_ROWS))
+        }
         MmmEventType::Backspace => {
             if filesys_state.filter_is_empty() {
                 Some(MmmStateUpdateType::NavBack)
@@ -102,40 +445,285 @@ pub fn process_state_update(
     state_update: MmmStateUpdateType,
     layout: &mut MmmLayout,
     filesys: &mut MmmFilesys,
+    status: &mut MmmStatus,
 ) -> MmmResult<DrawOps> {
     match state_update {
         MmmStateUpdateType::Exit => Err("unexpected exit state".into()),
         MmmStateUpdateType::Resize(col, row) => {
             layout.process_resize_event(vec2!(col, row)?)?;
-            Ok(DrawOps::new(true, true, true))
+            Ok(DrawOps::full())
         }
         MmmStateUpdateType::NavInto => {
             filesys.try_nav_into()?;
-            Ok(DrawOps::new(false, true, true))
+            Ok(DrawOps::directory())
         }
         MmmStateUpdateType::NavBack => {
             filesys.try_nav_back()?;
-            Ok(DrawOps::new(false, true, true))
+            Ok(DrawOps::directory())
         }
         MmmStateUpdateType::NextEntry => {
             filesys.increment_current_selected();
-            Ok(DrawOps::new(false, true, false))
+            Ok(DrawOps::selection())
         }
         MmmStateUpdateType::PrevEntry => {
             filesys.decrement_current_selected();
-            Ok(DrawOps::new(false, true, false))
+            Ok(DrawOps::selection())
         }
         MmmStateUpdateType::AddChar(c) => {
-            filesys.filter_add_char(c);
-            Ok(DrawOps::new(false, true, true))
+            filesys.filter_add_char(c)?;
+            Ok(DrawOps::directory())
         }
         MmmStateUpdateType::ClearSearch => {
             filesys.clear_filter();
-            Ok(DrawOps::new(false, true, true))
+            Ok(DrawOps::listing())
         }
         MmmStateUpdateType::ToggleHidden => {
             filesys.toggle_show_hidden_files()?;
-            Ok(DrawOps::new(false, true, false))
+            status.info(if filesys.show_hidden_files {
+                "showing hidden files"
+            } else {
+                "hiding hidden files"
+            });
+            Ok(DrawOps::directory())
+        }
+        MmmStateUpdateType::MakeDir => {
+            filesys.make_dir_from_filter()?;
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::Trash => {
+            filesys.trash_selected()?;
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::Yank => {
+            filesys.yank_selected(false);
+            if let Some(clipboard) = filesys.get_clipboard() {
+                status.info(format!("yanked {}", clipboard.paths.len()));
+            }
+            Ok(DrawOps::status_line())
+        }
+        MmmStateUpdateType::Cut => {
+            filesys.yank_selected(true);
+            if let Some(clipboard) = filesys.get_clipboard() {
+                status.info(format!("cut {}", clipboard.paths.len()));
+            }
+            Ok(DrawOps::status_line())
+        }
+        MmmStateUpdateType::Paste => {
+            filesys.paste();
+            Ok(DrawOps::status_line())
+        }
+        MmmStateUpdateType::Undo => {
+            filesys.undo()?;
+            status.info("undone");
+            Ok(DrawOps::directory())
+        }
+        MmmStateUpdateType::Redo => {
+            filesys.redo()?;
+            status.info("redone");
+            Ok(DrawOps::directory())
+        }
+        MmmStateUpdateType::Mark => {
+            filesys.toggle_mark_selected();
+            status.info(format!("{} marked", filesys.get_marked().len()));
+            Ok(DrawOps::selection())
+        }
+        MmmStateUpdateType::BulkRename => {
+            let names = filesys.get_rename_names();
+            let edited =
+                with_display_suspended(&mut layout.display, || edit_names_in_editor(&names))??;
+            filesys.bulk_rename(&names, &edited)?;
+            status.info("renamed");
+            Ok(DrawOps::full())
+        }
+        MmmStateUpdateType::CancelJobs => {
+            filesys.cancel_jobs();
+            Ok(DrawOps::status_line())
+        }
+        MmmStateUpdateType::CyclePolicy => {
+            filesys.cycle_conflict_policy();
+            status.info(format!(
+                "on conflict {}",
+                filesys.get_conflict_policy().get_name()
+            ));
+            Ok(DrawOps::status_line())
+        }
+        MmmStateUpdateType::Tick => {
+            status.expire();
+            // A failed background job shouldn't take the rest of the program down with it, a
+            // finished one has changed the listing, otherwise only its progress moved
+            match filesys.poll_jobs() {
+                Ok(Some(summary)) => {
+                    status.info(summary);
+                    Ok(DrawOps::listing())
+                }
+                Ok(None) => Ok(DrawOps::status_line()),
+                Err(err) => {
+                    status.error(err.to_string());
+                    Ok(DrawOps::listing())
+                }
+            }
+        }
+        MmmStateUpdateType::AwaitBookmarkKey => {
+            filesys.await_bookmark_key();
+            status.info("press a key to bookmark this directory");
+            Ok(DrawOps::status_line())
+        }
+        MmmStateUpdateType::SetBookmark(c) => {
+            filesys.set_bookmark(c)?;
+            status.info(format!("bookmarked under '{}'", c));
+            Ok(DrawOps::status_line())
+        }
+        MmmStateUpdateType::JumpToBookmark(c) => {
+            filesys.jump_to_bookmark(c)?;
+            Ok(DrawOps::directory())
+        }
+        MmmStateUpdateType::OpenBookmarks => {
+            filesys.open_bookmarks();
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::DeleteBookmark => {
+            filesys.delete_selected_bookmark()?;
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::RenameBookmark => {
+            filesys.start_bookmark_rename();
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::FinishBookmarkRename => {
+            filesys.finish_bookmark_rename()?;
+            status.info("bookmark renamed");
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::CancelPrompt => {
+            filesys.cancel_prompt();
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::AddPromptChar(c) => {
+            filesys.prompt_add_char(c);
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::DeletePromptChar => {
+            filesys.prompt_pop_char();
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::CloseMode => {
+            filesys.close_mode();
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::OpenJump => {
+            filesys.open_jump();
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::OpenCommands => {
+            filesys.open_commands()?;
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::RunCommand => {
+            let Some((name, command)) = filesys.get_selected_command() else {
+                return Ok(DrawOps::nothing());
+            };
+            filesys.close_mode();
+            let cwd = filesys.get_current_path().to_path_buf();
+            let exit =
+                with_display_suspended(&mut layout.display, || run_shell_command(&command, &cwd))??;
+            filesys.refresh()?;
+            if exit.success() {
+                status.info(format!("{} finished", name));
+            } else {
+                status.error(format!("{} failed, {}", name, exit));
+            }
+            Ok(DrawOps::full())
+        }
+        MmmStateUpdateType::Subshell => {
+            let cwd = filesys.get_current_path().to_path_buf();
+            with_display_suspended(&mut layout.display, || run_subshell(&cwd))??;
+            filesys.refresh()?;
+            Ok(DrawOps::full())
+        }
+        MmmStateUpdateType::Suspend => {
+            suspend_process(&mut layout.display)?;
+            // The terminal may have been resized while stopped, so lay out and draw everything
+            // again as for a resize
+            layout.process_resize_event(get_terminal_size()?)?;
+            Ok(DrawOps::full())
+        }
+        MmmStateUpdateType::HistoryBack => {
+            filesys.history_back()?;
+            Ok(DrawOps::directory())
+        }
+        MmmStateUpdateType::HistoryForward => {
+            filesys.history_forward()?;
+            Ok(DrawOps::directory())
+        }
+        MmmStateUpdateType::OpenGoTo(c) => {
+            filesys.open_go_to(c);
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::DeleteChar => {
+            filesys.filter_pop_char();
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::CompletePath => {
+            filesys.complete_path();
+            Ok(DrawOps::listing())
+        }
+        MmmStateUpdateType::GoToPath => {
+            filesys.go_to_path()?;
+            Ok(DrawOps::directory())
+        }
+        MmmStateUpdateType::NextSibling => {
+            filesys.change_to_sibling(true)?;
+            Ok(DrawOps::directory())
+        }
+        MmmStateUpdateType::PrevSibling => {
+            filesys.change_to_sibling(false)?;
+            Ok(DrawOps::directory())
+        }
+        MmmStateUpdateType::Click(col, row) => {
+            if let Some(pane_row) = layout.get_current_row(col, row) {
+                filesys.select_index(filesys.current_top_row + pane_row);
+                Ok(DrawOps::selection())
+            } else if let Some(pane_row) = layout.get_parent_row(col, row) {
+                filesys.nav_to_parent_entry(get_parent_pane_top_row(layout, filesys) + pane_row)?;
+                Ok(DrawOps::directory())
+            } else {
+                Ok(DrawOps::nothing())
+            }
+        }
+        MmmStateUpdateType::DoubleClick(col, row) => {
+            // The first click already selected the entry, or went up to the parent
+            if let Some(pane_row) = layout.get_current_row(col, row) {
+                filesys.select_index(filesys.current_top_row + pane_row);
+                filesys.try_nav_into()?;
+                Ok(DrawOps::directory())
+            } else {
+                Ok(DrawOps::nothing())
+            }
+        }
+        MmmStateUpdateType::Scroll(col, row, delta) => {
+            if layout.get_current_row(col, row).is_some() {
+                filesys.scroll_current(delta, layout.current_size.row as usize);
+            } else if layout.get_parent_row(col, row).is_some() {
+                let top_row = get_parent_pane_top_row(layout, filesys);
+                filesys.scroll_parent(top_row, delta, layout.parent_size.row as usize);
+            }
+            Ok(DrawOps::directory())
+        }
+        MmmStateUpdateType::Pick => {
+            filesys.pick()?;
+            Ok(DrawOps::directory())
         }
     }
 }
+
+fn get_parent_pane_top_row(layout: &MmmLayout, filesys: &MmmFilesys) -> usize {
+    get_parent_top_row(
+        filesys
+            .filtered_parent_dir_list
+            .as_ref()
+            .map_or(0, Vec::len),
+        filesys.parent_current_entry,
+        layout.parent_size.row,
+        filesys.parent_top_row,
+    )
+}
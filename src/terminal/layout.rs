@@ -1,9 +1,10 @@
-use std::cmp::min;
+use std::cmp::{max, min};
 
-use doubuff::helpers::stop_display;
 use terminal_vec2::{vec2, Vec2};
 
-use crate::error_type::MmmResult;
+use crate::error_type::{MmmError, MmmResult};
+
+use super::display::MmmDisplay;
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct MmmLayout {
@@ -21,22 +22,18 @@ pub struct MmmLayout {
     pub path_disp_pos: Vec2,
     pub path_disp_width: usize,
     pub search_width: usize,
+    pub jobs_pos: Vec2,
+    pub jobs_width: usize,
+    pub status_sep_pos: Vec2,
+    pub status_pos: Vec2,
+    pub status_width: usize,
+    pub display: MmmDisplay,
 }
 
 impl MmmLayout {
-    pub fn new() -> MmmResult<Self> {
-        let (col, row) = crossterm::terminal::size().expect("Unable to determine terminal size");
-        #[cfg(not(target_os = "windows"))]
-        let terminal_size: Vec2 = (col, row).into();
-        #[cfg(target_os = "windows")]
-        let terminal_size: Vec2 = (col + 1, row + 1).into();
-        Self::from_size(terminal_size)
-    }
-
     pub fn from_size(term_size: Vec2) -> MmmResult<Self> {
         if term_size.col < 10 || term_size.row < 4 {
-            stop_display()?;
-            Err("display too small")?;
+            Err(MmmError::Terminal("display too small".to_string()))?;
         }
         let app_size = vec2!(
             min(term_size.col, 98),
@@ -46,10 +43,15 @@ impl MmmLayout {
             (term_size.col - app_size.col) / 2,
             (term_size.row - app_size.row) / 2
         )?;
+        // The status line and its separator are dropped when there isn't room for them
+        let status_rows = if app_size.row >= 8 { 2 } else { 0 };
         let parent_pos = app_pos + vec2!(1, 3)?;
-        let parent_size = vec2!(min((app_size.col * 31) / 98, 31), app_size.row - 4)?;
+        let parent_size = vec2!(
+            min((app_size.col * 31) / 98, 31),
+            app_size.row - 4 - status_rows
+        )?;
         let vert_sep_pos = app_pos + vec2!(parent_size.col + 1, 2)?;
-        let vert_sep_size = vec2!(1, app_size.row - 2)?;
+        let vert_sep_size = vec2!(1, app_size.row - 2 - status_rows)?;
         let current_pos = app_pos + vec2!(parent_size.col + 2, 3)?;
         let current_size = vec2!(
             app_size
@@ -63,6 +65,15 @@ impl MmmLayout {
         let search_width = min(app_size.col as usize - 2, 20);
         let path_disp_width = app_size.col as usize - 2 - search_width;
         let path_disp_pos = app_pos + vec2!(1, 1)?;
+        let jobs_pos = app_pos + vec2!(2, app_size.row - 1)?;
+        let jobs_width = (app_size.col as usize).saturating_sub(4);
+        let status_sep_pos = app_pos + vec2!(0, app_size.row - 3)?;
+        let status_pos = app_pos + vec2!(1, app_size.row - 2)?;
+        let status_width = if status_rows > 0 {
+            app_size.col as usize - 2
+        } else {
+            0
+        };
 
         Ok(MmmLayout {
             term_size,
@@ -79,6 +90,62 @@ impl MmmLayout {
             search_width,
             path_disp_width,
             path_disp_pos,
+            jobs_pos,
+            jobs_width,
+            status_sep_pos,
+            status_pos,
+            status_width,
+            display: MmmDisplay::default(),
         })
     }
+
+    /// The row of the current pane at a terminal position, if it is inside the pane
+    pub fn get_current_row(&self, col: u16, row: u16) -> Option<usize> {
+        get_pane_row(self.current_pos, self.current_size, col, row)
+    }
+
+    /// The row of the parent pane at a terminal position, if it is inside the pane
+    pub fn get_parent_row(&self, col: u16, row: u16) -> Option<usize> {
+        get_pane_row(self.parent_pos, self.parent_size, col, row)
+    }
+}
+
+/// The size of the real terminal, headless runs pick their own
+pub fn get_terminal_size() -> MmmResult<Vec2> {
+    let (col, row) = crossterm::terminal::size()
+        .map_err(|err| MmmError::Terminal(format!("cannot get the terminal size, {}", err)))?;
+    #[cfg(not(target_os = "windows"))]
+    let terminal_size: Vec2 = (col, row).into();
+    #[cfg(target_os = "windows")]
+    let terminal_size: Vec2 = (col + 1, row + 1).into();
+    Ok(terminal_size)
+}
+
+fn get_pane_row(pos: Vec2, size: Vec2, col: u16, row: u16) -> Option<usize> {
+    let inside = (pos.col..pos.col + size.col).contains(&col)
+        && (pos.row..pos.row + size.row).contains(&row);
+    inside.then(|| (row - pos.row) as usize)
+}
+
+/// The first entry shown in the parent pane, centred on the selected entry unless the pane has
+/// been scrolled
+pub fn get_parent_top_row(
+    len: usize,
+    selected: usize,
+    rows: u16,
+    scrolled: Option<usize>,
+) -> usize {
+    if let Some(top_row) = scrolled {
+        return top_row;
+    }
+    // Calculate the top row using clamped centering formula
+    max(
+        min(
+            selected as i32 - ((rows as i32 - 1) / 2),
+            (len as i32) - (1 + (rows as i32)),
+        ),
+        0,
+    )
+    .try_into()
+    .expect("unable to convert top_row to usize")
 }
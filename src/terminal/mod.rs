@@ -1,3 +1,6 @@
+pub(crate) mod canvas;
+pub(crate) mod display;
 pub(crate) mod draw;
 pub(crate) mod events;
 pub(crate) mod layout;
+pub(crate) mod status;
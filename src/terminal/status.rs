@@ -0,0 +1,64 @@
+use std::time::{Duration, Instant};
+
+const MESSAGE_DURATION: Duration = Duration::from_secs(4);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MmmMessageKind {
+    Info,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct MmmMessage {
+    pub text: String,
+    pub kind: MmmMessageKind,
+    expires: Instant,
+}
+
+/// Transient messages shown in the status line in place of the selected entry's details
+#[derive(Debug, Default)]
+pub struct MmmStatus {
+    message: Option<MmmMessage>,
+}
+
+impl MmmStatus {
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.set_message(text.into(), MmmMessageKind::Info);
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.set_message(text.into(), MmmMessageKind::Error);
+    }
+
+    fn set_message(&mut self, text: String, kind: MmmMessageKind) {
+        self.message = Some(MmmMessage {
+            text,
+            kind,
+            expires: Instant::now() + MESSAGE_DURATION,
+        });
+    }
+
+    pub fn get_message(&self) -> Option<&MmmMessage> {
+        self.message.as_ref()
+    }
+
+    /// Time left until the current message should be cleared
+    pub fn time_to_expiry(&self) -> Option<Duration> {
+        self.message
+            .as_ref()
+            .map(|message| message.expires.saturating_duration_since(Instant::now()))
+    }
+
+    /// Clears the message once it has expired, returns whether it was cleared
+    pub fn expire(&mut self) -> bool {
+        if self
+            .message
+            .as_ref()
+            .is_some_and(|message| message.expires <= Instant::now())
+        {
+            self.message = None;
+            return true;
+        }
+        false
+    }
+}
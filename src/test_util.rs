@@ -0,0 +1,117 @@
+//! Fixtures shared by the tests of every module
+
+use std::{
+    collections::VecDeque,
+    fs,
+    future::Future,
+    io,
+    ops::Deref,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use futures::future::ready;
+use terminal_vec2::Vec2;
+
+use crate::{
+    app::{MmmApp, MmmEventSource},
+    config::MmmConfig,
+    error_type::MmmResult,
+    terminal::{canvas::MmmTextCanvas, status::MmmStatus},
+};
+
+/// A directory made for a test, removed along with everything in it when dropped
+#[derive(Debug)]
+pub struct MmmTestDir(PathBuf);
+
+impl Deref for MmmTestDir {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &PathBuf {
+        &self.0
+    }
+}
+
+impl Drop for MmmTestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+        // The directories it was made in go too once they are empty, up to and including the
+        // base for this run
+        let base = get_test_base();
+        for parent in self.0.ancestors().skip(1) {
+            if !parent.starts_with(&base) || fs::remove_dir(parent).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// A fixed script of events, running out of events ends the run
+pub struct MmmScriptedEvents(pub VecDeque<Event>);
+
+impl MmmEventSource for MmmScriptedEvents {
+    fn next_event(&mut self) -> impl Future<Output = Option<io::Result<Event>>> {
+        ready(self.0.pop_front().map(Ok))
+    }
+}
+
+pub fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+/// The key events for typing `text`
+pub fn type_str(text: &str) -> Vec<Event> {
+    text.chars().map(|c| key(KeyCode::Char(c))).collect()
+}
+
+fn get_test_base() -> PathBuf {
+    std::env::temp_dir().join(format!("mmm-tests-{}", std::process::id()))
+}
+
+/// A fresh data directory for each app, so tests never write bookmarks, history or trash to the
+/// real one or to each other's. It must outlive anything that writes to it.
+pub fn make_data_home() -> MmmTestDir {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    MmmTestDir(get_test_base().join(format!("data-{}", NEXT.fetch_add(1, Ordering::Relaxed))))
+}
+
+/// Creates a fresh directory tree for a test
+pub fn make_tree(name: &str, dirs: &[&str], files: &[&str]) -> MmmTestDir {
+    let root = MmmTestDir(get_test_base().join(name));
+    let _ = fs::remove_dir_all(&*root);
+    fs::create_dir_all(&*root).expect("cannot create test directory");
+    for dir in dirs {
+        fs::create_dir_all(root.join(dir)).expect("cannot create test directory");
+    }
+    for file in files {
+        fs::write(root.join(file), "").expect("cannot create test file");
+    }
+    root
+}
+
+/// Runs a script of events against `path` headless, returning the app and the last frame
+pub async fn run_script(
+    path: PathBuf,
+    size: Vec2,
+    events: Vec<Event>,
+) -> MmmResult<(MmmApp, MmmTextCanvas)> {
+    let data_home = make_data_home();
+    let app = MmmApp::new(
+        path,
+        size,
+        &MmmConfig::default(),
+        Some(&data_home),
+        None,
+        MmmStatus::default(),
+    )?;
+    run_app(app, events).await
+}
+
+/// Runs a script of events against an app set up by the test
+pub async fn run_app(mut app: MmmApp, events: Vec<Event>) -> MmmResult<(MmmApp, MmmTextCanvas)> {
+    let mut canvas = MmmTextCanvas::default();
+    app.run(&mut MmmScriptedEvents(events.into()), &mut canvas)
+        .await?;
+    Ok((app, canvas))
+}